@@ -7,7 +7,7 @@ struct WebSocketHandler;
 impl EventHandler for WebSocketHandler {
     fn on_connect(&mut self, event: NotificationEvent) {
         if let NotificationEvent::Info(info) = event {
-            println!("Platform status: {:?}, Version {}", info.platform, info.version);
+            println!("Platform status: {:?}, Version {:?}", info.platform, info.version);
         }
     }
 
@@ -26,7 +26,8 @@ impl EventHandler for WebSocketHandler {
     }
 }
 
-fn main() {
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
     let api_key = "YOUR_API_KEY";
     let secret_key = "YOUR_SECRET_KEY";
     let mut web_socket: WebSockets = WebSockets::new();
@@ -34,7 +35,7 @@ fn main() {
     web_socket.add_event_handler(WebSocketHandler);
     web_socket.connect().unwrap(); // check error
 
-    web_socket.auth(api_key, secret_key, false, &[]).unwrap();
+    web_socket.auth(api_key, secret_key, false, &[]).await.unwrap();
 
     // TODO: Handle authenticated channels
 
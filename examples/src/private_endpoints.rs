@@ -5,33 +5,34 @@ use bitfinex::api::*;
 use bitfinex::pairs::*;
 use bitfinex::currency::*;
 
-fn main() {
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
     let api_key = Some("YOUR_API_KEY".into());
     let secret_key = Some("YOUR_SECRET_KEY".into());
     let api = Bitfinex::new(api_key, secret_key);
   
     // ORDERS
-    match api.orders.active_orders() {
+    match api.orders.active_orders().await {
         Ok(orders) => {
             for order in &orders {
-                println!("Active orders => Symbol: {:?} amount: {:?} price: {:?}", order.symbol, order.amount, order.price);
+                println!("Active orders => Symbol: {:?} amount: {:?} price: {:?}", order.symbol(), order.amount(), order.price());
             }    
         },
         Err(e) => println!("Error: {}", e),
     }     
 
-    let order_history = api.orders.history(BTCUSD.to_owned()); // Use None if you don't want a pair 
+    let order_history = api.orders.history(Some(BTCUSD)).await; // Use None if you don't want a pair 
     match order_history {
         Ok(orders) => {
             for order in &orders {
-                println!("Order History => Symbol: {:?} amount: {:?} price: {:?}", order.symbol, order.amount, order.price);
+                println!("Order History => Symbol: {:?} amount: {:?} price: {:?}", order.symbol(), order.amount(), order.price());
             }    
         },
         Err(e) => println!("Error: {}", e),
     }
 
     // WALLET
-    match api.account.get_wallets() {
+    match api.account.get_wallets().await {
         Ok(wallets) => {
             for wallet in &wallets {
                 println!("Wallet => Currency: {:?} Balance: {:?}", wallet.currency, wallet.balance);
@@ -41,14 +42,14 @@ fn main() {
     }
 
     // MARGIN INFO
-    match api.account.margin_base() {
+    match api.account.margin_base().await {
         Ok(info) => {
             println!("Margin Base Info => Profile/Loss: {:?}", info.margin.user_profit_loss);    
         },
         Err(e) => println!("Error: {}", e),
     }
 
-    match api.account.margin_symbol(ETHUSD) {
+    match api.account.margin_symbol(ETHUSD).await {
         Ok(info) => {
             println!("Margin Symbol Info => Gross Balance: {:?}", info.margin.gross_balance);    
         },
@@ -56,7 +57,7 @@ fn main() {
     }   
 
     // FUNDING INFO
-    match api.account.funding_info(USD) {
+    match api.account.funding_info(USD).await {
         Ok(info) => {
             println!("Funding Info => Yield Loan: {:?} Yield Lend: {:?}", info.funding.yield_loan, info.funding.yield_lend);    
         },
@@ -65,7 +66,7 @@ fn main() {
 
     // LEDGER
     let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-    match api.ledger.get_history(USD, now - 3600000, now, 5) {
+    match api.ledger.get_history(USD, now - 3600000, now, 5).await {
         Ok(entries) => {
             for entry in &entries {
                 println!("Ledger Entry => {}{} => {}: {}", entry.amount, entry.currency, entry.balance, entry.description);
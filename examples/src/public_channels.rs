@@ -1,14 +1,15 @@
 extern crate bitfinex;
 
-use bitfinex::{ errors::*, events::*, websockets::* };
-use bitfinex::{ pairs::*, precision::* };
+use bitfinex::{ candles::*, errors::*, events::*, websockets::* };
+use bitfinex::book::{ BookFrequency, BookLength, BookPrecision };
+use bitfinex::pairs::*;
 
 struct WebSocketHandler;
 
 impl EventHandler for WebSocketHandler {
     fn on_connect(&mut self, event: NotificationEvent) {
         if let NotificationEvent::Info(info) = event {
-            println!("Platform status: {:?}, Version {}", info.platform, info.version);
+            println!("Platform status: {:?}, Version {:?}", info.platform, info.version);
         }
     }
 
@@ -51,13 +52,13 @@ fn main() {
     web_socket.subscribe_trades(BTCUSD, EventType::Trading);
 
     // BOOKS
-    web_socket.subscribe_books(BTCUSD, EventType::Trading, P0, "F0", 25);
+    web_socket.subscribe_books(BTCUSD, EventType::Trading, BookPrecision::P0, BookFrequency::F0, BookLength::L25).unwrap(); // check error
 
     // RAW BOOKS
     web_socket.subscribe_raw_books(BTCUSD, EventType::Trading);
 
     // CANDLES
-    web_socket.subscribe_candles(BTCUSD, "1m");
+    web_socket.subscribe_candles(CandleKey::trading(BTCUSD, CandlesTimeFrame::OneMinute));
 
     web_socket.event_loop().unwrap(); // check error
 }
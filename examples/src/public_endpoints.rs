@@ -3,26 +3,28 @@ extern crate bitfinex;
 use bitfinex::api::*;
 use bitfinex::pairs::*;
 use bitfinex::currency::*;
-use bitfinex::precision::*;
+use bitfinex::book::BookPrecision::*;
+use bitfinex::candles::CandlesTimeFrame;
 
-fn main() {
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
     let api = Bitfinex::new(None, None);
   
     // TICKER
-    let trading_pair = api.ticker.trading_pair(ETHUSD);
+    let trading_pair = api.ticker.trading_pair(ETHUSD).await;
     match trading_pair {
         Ok(answer) => println!("bid: {:?}  ask: {:?}", answer.bid, answer.ask),
         Err(e) => println!("Error: {}", e),
     }   
 
-    let funding_currency = api.ticker.funding_currency(USD);
+    let funding_currency = api.ticker.funding_currency(USD).await;
     match funding_currency {
         Ok(answer) => println!("bid: {:?}  ask: {:?}", answer.bid, answer.ask),
         Err(e) => println!("Error: {}", e),
     }
 
     // TRADES
-    let trading_pairs = api.trades.trading_pair(ETHUSD);
+    let trading_pairs = api.trades.trading_pair(ETHUSD).await;
     match trading_pairs {
         Ok(trades) => {
             for trade in &trades {
@@ -32,7 +34,7 @@ fn main() {
         Err(e) => println!("Error: {}", e),
     }   
 
-    let funding_currency = api.trades.funding_currency(USD);
+    let funding_currency = api.trades.funding_currency(USD).await;
     match funding_currency {
         Ok(trades) => {
             for trade in &trades {
@@ -43,7 +45,7 @@ fn main() {
     }        
 
     // BOOK
-    let trading_pairs = api.book.trading_pair(ETHUSD, P0);
+    let trading_pairs = api.book.trading_pair(ETHUSD, P0).await;
     match trading_pairs {
         Ok(books) => {
             for book in &books {
@@ -53,7 +55,7 @@ fn main() {
         Err(e) => println!("Error: {}", e),
     }   
 
-    let funding_currency = api.book.funding_currency(USD, P0);
+    let funding_currency = api.book.funding_currency(USD, P0).await;
     match funding_currency {
         Ok(books) => {
             for book in &books {
@@ -64,13 +66,13 @@ fn main() {
     }  
 
     // CANDLES
-    let last = api.candles.last(ETHUSD, "1m");
+    let last = api.candles.last(ETHUSD, CandlesTimeFrame::OneMinute).await;
     match last {
         Ok(answer) => println!("Candle Last => High: {:?} low: {:?}", answer.high, answer.low),
         Err(e) => println!("Error: {}", e),
     }    
 
-    let history = api.candles.history(ETHUSD, "12h");
+    let history = api.candles.history(ETHUSD, CandlesTimeFrame::TwelveHours).await;
     match history {
         Ok(candles) => {
             for candle in &candles {
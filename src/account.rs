@@ -1,17 +1,20 @@
-use serde_json::from_str;
-
 use crate::client::Client;
 use crate::endpoints::{AuthenticatedEndpoint, MarginInfoKey};
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 use crate::responses::{AccountFees, WalletTransferResponse};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Wallet {
     pub wallet_type: WalletKind,
     pub currency: String,
     pub balance: f64,
     pub unsettled_interest: f64,
     pub balance_available: Option<f64>,
+    /// Description of the last change of the balance
+    #[serde(default)]
+    pub last_change: Option<String>,
+    #[serde(default)]
+    pub last_change_meta: Option<serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -24,52 +27,54 @@ pub enum WalletKind {
     Funding,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct MarginBase {
     key: String,
     pub margin: Base,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Base {
     pub user_profit_loss: f64,
     pub user_swaps: f64,
     pub margin_balance: f64,
     pub margin_net: f64,
+    #[serde(default)]
+    pub margin_min: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct MarginSymbol {
     key: String,
     symbol: String,
     pub margin: Symbol,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Symbol {
     pub tradable_balance: f64,
     pub gross_balance: f64,
     pub buy: f64,
     pub sell: f64,
 
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     _placeholder_1: Option<String>,
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     _placeholder_2: Option<String>,
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     _placeholder_3: Option<String>,
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     _placeholder_4: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct FundingInfo {
     key: String,
     symbol: String,
     pub funding: Funding,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Funding {
     pub yield_loan: f64,
     pub yield_lend: f64,
@@ -77,6 +82,13 @@ pub struct Funding {
     pub duration_lend: f64,
 }
 
+/// The assets under management of the account, pushed on the websocket (`bu`).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Balance {
+    pub aum: f64,
+    pub aum_net: f64,
+}
+
 #[derive(Clone)]
 pub struct Account {
     client: Client,
@@ -87,11 +99,11 @@ impl Account {
         Account { client }
     }
 
-    pub async fn get_wallets(&self) -> Result<Vec<Wallet>, BoxError> {
+    pub async fn get_wallets(&self) -> Result<Vec<Wallet>, Error> {
         let endpoint = AuthenticatedEndpoint::Wallets;
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
     pub async fn transfer_between_wallets(
@@ -100,7 +112,7 @@ impl Account {
         to: &WalletKind,
         currency: String,
         amount: f64,
-    ) -> Result<WalletTransferResponse, BoxError> {
+    ) -> Result<WalletTransferResponse, Error> {
         let endpoint = AuthenticatedEndpoint::WalletTransfer;
         let payload =
             json!({"from": from, "to": to, "currency": currency, "amount": amount.to_string()});
@@ -110,41 +122,41 @@ impl Account {
             .post_signed(&endpoint, payload.to_string())
             .await?;
 
-        Ok(from_str(&response)?)
+        from_json(&response)
     }
 
-    pub async fn margin_base(&self) -> Result<MarginBase, BoxError> {
+    pub async fn margin_base(&self) -> Result<MarginBase, Error> {
         let endpoint = AuthenticatedEndpoint::MarginInfo {
             key: MarginInfoKey::Base,
         };
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 
-    pub async fn margin_symbol<S: ToString>(&self, key: S) -> Result<MarginSymbol, BoxError> {
+    pub async fn margin_symbol<S: ToString>(&self, key: S) -> Result<MarginSymbol, Error> {
         let endpoint = AuthenticatedEndpoint::MarginInfo {
             key: MarginInfoKey::Symbol(key.to_string()),
         };
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
-    pub async fn funding_info<S>(&self, key: S) -> Result<FundingInfo, BoxError>
+    pub async fn funding_info<S>(&self, key: S) -> Result<FundingInfo, Error>
     where
         S: Into<String>,
     {
         let endpoint = AuthenticatedEndpoint::FundingInfo { symbol: key.into() };
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
-    pub async fn account_summary(&self) -> Result<AccountFees, BoxError> {
+    pub async fn account_summary(&self) -> Result<AccountFees, Error> {
         let endpoint = AuthenticatedEndpoint::Summary;
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 }
@@ -1,9 +1,15 @@
+use std::sync::Arc;
+
 use crate::account::Account;
 use crate::book::Book;
 use crate::candles::Candles;
+use crate::client::Client;
+use crate::config::ClientConfig;
+use crate::errors::Error;
 use crate::ledger::Ledger;
 use crate::orders::Orders;
 use crate::positions::Positions;
+use crate::signer::Signer;
 use crate::ticker::Ticker;
 use crate::trades::Trades;
 
@@ -20,9 +26,42 @@ pub struct Bitfinex {
 }
 
 impl Bitfinex {
+    /// Creates the API with the default configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend cannot be initialized, like `reqwest::Client::new`. Use
+    /// [`Bitfinex::try_new`] to handle that failure instead.
     pub fn new(api_key: Option<String>, secret_key: Option<String>) -> Self {
-        let client = crate::client::Client::new(api_key.clone(), secret_key.clone());
+        Bitfinex::try_new(api_key, secret_key).expect("Failed to build the HTTP client")
+    }
+
+    /// Creates the API with the default configuration, failing if the TLS backend cannot be
+    /// initialized.
+    pub fn try_new(api_key: Option<String>, secret_key: Option<String>) -> Result<Self, Error> {
+        Bitfinex::with_config(api_key, secret_key, ClientConfig::default())
+    }
+
+    /// Creates the API with a custom configuration.
+    ///
+    /// Fails if the proxy URL or a root certificate of `config` is invalid.
+    pub fn with_config(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        config: ClientConfig,
+    ) -> Result<Self, Error> {
+        Ok(Bitfinex::with_client(Client::with_config(
+            api_key, secret_key, config,
+        )?))
+    }
+
+    /// Creates the API using a custom [`Signer`], so the secret never has to be handed to the
+    /// library.
+    pub fn with_signer(signer: Arc<dyn Signer>, config: ClientConfig) -> Result<Self, Error> {
+        Ok(Bitfinex::with_client(Client::with_signer(signer, config)?))
+    }
 
+    fn with_client(client: Client) -> Self {
         Bitfinex {
             book: Book::new(client.clone()),
             ticker: Ticker::new(client.clone()),
@@ -0,0 +1,531 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{SinkExt, Stream, StreamExt};
+use tokio::sync::mpsc;
+use tungstenite::Message;
+
+use crate::book::{BookFrequency, BookLength, BookPrecision};
+use crate::candles::CandleKey;
+use crate::config::ClientConfig;
+use crate::connector::{connect_websocket_async, AsyncSocket};
+use crate::errors::Error;
+use crate::events::WebSocketEvent;
+use crate::orders::{
+    CancelOrderForm, CancelOrdersForm, OrderForm, OrderOperation, UpdateOrderForm,
+};
+use crate::protocol::{
+    conf_message, is_connection_error, Auth, Decoder, OrderMessage, OrderRequests, PendingOrder,
+    Session, Unsubscribe, Watchdog,
+};
+use crate::retry::ReconnectPolicy;
+use crate::signer::{HmacSigner, Signer};
+use crate::websockets::{ConfFlags, EventType, OrderRequest, Subscription};
+
+static COMMAND_BUFFER: usize = 64;
+static EVENT_BUFFER: usize = 1024;
+
+#[derive(Debug)]
+enum Command {
+    Send(String),
+    Subscribe(Subscription),
+    Unsubscribe(Unsubscribe),
+    Auth(Auth, String),
+    Configure(ConfFlags),
+    Order(String, PendingOrder),
+    Close,
+}
+
+/// Connects to the websocket of `config` and spawns a task driving the connection on the
+/// current tokio runtime.
+///
+/// Commands are sent through the returned [`WebSocketHandle`], which can be cloned and shared
+/// between tasks. Inbound messages are read from the [`EventStream`]. The connection is closed
+/// when [`WebSocketHandle::close`] is called or when the stream is dropped. With a reconnect
+/// policy, dropped connections are re-established and reported with
+/// [`WebSocketEvent::Disconnected`] and [`WebSocketEvent::Reconnected`].
+///
+/// ```no_run
+/// use bitfinex::async_websockets;
+/// use bitfinex::config::ClientConfig;
+/// use bitfinex::websockets::EventType;
+/// use futures::StreamExt;
+///
+/// # async fn run() -> Result<(), bitfinex::errors::Error> {
+/// let (handle, mut events) = async_websockets::connect(ClientConfig::default()).await?;
+///
+/// handle.subscribe_ticker("BTCUSD", EventType::Trading).await?;
+///
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn connect(config: ClientConfig) -> Result<(WebSocketHandle, EventStream), Error> {
+    let (events, stream) = event_channel();
+    let handle = spawn_connection(config, events).await?;
+
+    Ok((handle, stream))
+}
+
+/// Feeds the [`EventStream`] of a connection.
+pub(crate) type EventSender = mpsc::Sender<Result<WebSocketEvent, Error>>;
+
+pub(crate) fn event_channel() -> (EventSender, EventStream) {
+    let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+
+    (tx, EventStream { rx })
+}
+
+/// Connects like [`connect`], sending the events of the connection to `events`.
+pub(crate) async fn spawn_connection(
+    config: ClientConfig,
+    events: EventSender,
+) -> Result<WebSocketHandle, Error> {
+    let socket = connect_websocket_async(&config).await?;
+
+    let (commands_tx, commands_rx) = mpsc::channel(COMMAND_BUFFER);
+
+    let connection = Connection {
+        socket,
+        session: Session::default(),
+        decoder: Decoder::default(),
+        orders: OrderRequests::default(),
+        watchdog: Watchdog::new(&config),
+        config: config.clone(),
+        commands: commands_rx,
+        handles_alive: true,
+        events,
+    };
+
+    tokio::spawn(connection.run());
+
+    Ok(WebSocketHandle {
+        tx: commands_tx,
+        config,
+    })
+}
+
+struct Connection {
+    socket: AsyncSocket,
+    session: Session,
+    decoder: Decoder,
+    orders: OrderRequests,
+    watchdog: Watchdog,
+    config: ClientConfig,
+    commands: mpsc::Receiver<Command>,
+    // the connection outlives the handles, until the event stream is dropped
+    handles_alive: bool,
+    events: EventSender,
+}
+
+impl Connection {
+    async fn run(mut self) {
+        loop {
+            let error = match self.read().await {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+
+            let policy = match self.config.reconnect_policy() {
+                Some(policy) if is_connection_error(&error) => policy.clone(),
+                // the server is about to restart, it is reconnected once regardless
+                None if self.decoder.reconnect_requested() => ReconnectPolicy::requested(),
+                _ => {
+                    let _ = self.events.send(Err(error)).await;
+                    return;
+                }
+            };
+
+            if !self.reconnect(error, &policy).await {
+                return;
+            }
+        }
+    }
+
+    /// Runs the connection until it drops. Returns `Ok` when it was closed on purpose.
+    async fn read(&mut self) -> Result<(), Error> {
+        let tick = Watchdog::tick(&self.config);
+        let mut watchdog = tokio::time::interval(tick.unwrap_or(Duration::from_secs(1)));
+
+        loop {
+            tokio::select! {
+                _ = watchdog.tick(), if tick.is_some() => {
+                    if let Some(ping) = self.watchdog.check(&self.decoder)? {
+                        self.socket.send(Message::Text(ping)).await?;
+                    }
+                }
+                command = self.commands.recv(), if self.handles_alive => match command {
+                    Some(Command::Close) => {
+                        let _ = self.socket.close(None).await;
+                        return Ok(());
+                    }
+                    Some(command) => self.execute(command).await?,
+                    None => self.handles_alive = false,
+                },
+                message = self.socket.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let events = match self.decoder.decode(&text) {
+                            Ok(events) => events.into_iter().map(Ok).collect(),
+                            Err(e) => vec![Err(e)],
+                        };
+
+                        for event in events {
+                            if let Ok(ref event) = event {
+                                self.orders.resolve(event);
+                            }
+
+                            if self.events.send(event).await.is_err() {
+                                let _ = self.socket.close(None).await;
+                                return Ok(());
+                            }
+                        }
+
+                        for reply in self.decoder.take_replies() {
+                            self.socket.send(Message::Text(reply)).await?;
+                        }
+
+                        if self.decoder.reconnect_requested() {
+                            return Err(Error::Disconnected(
+                                "Reconnect requested by the server".into(),
+                            ));
+                        }
+
+                        if !self.decoder.maintenance() {
+                            for text in self.orders.release() {
+                                self.socket.send(Message::Text(text)).await?;
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        return Err(Error::Disconnected(format!("{:?}", frame)));
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err(Error::Disconnected("Connection closed".into())),
+                },
+            }
+        }
+    }
+
+    async fn execute(&mut self, command: Command) -> Result<(), Error> {
+        // subscriptions and authentication are recorded first, so they are restored if the
+        // send fails
+        let text = match command {
+            Command::Send(text) => text,
+            Command::Subscribe(subscription) => {
+                let text = subscription.message();
+                self.session.subscribed(subscription);
+                text
+            }
+            Command::Unsubscribe(target) => {
+                match self.session.unsubscribed(target, &self.decoder) {
+                    Ok(text) => text,
+                    // an unknown channel does not affect the connection
+                    Err(e) => {
+                        let _ = self.events.send(Err(e)).await;
+                        return Ok(());
+                    }
+                }
+            }
+            Command::Auth(auth, text) => {
+                self.session.authenticated(auth);
+                self.orders.authenticating();
+                text
+            }
+            Command::Configure(flags) => {
+                self.session.configured(flags);
+                conf_message(flags)
+            }
+            Command::Order(text, pending) => {
+                match self.orders.send(text, pending, self.decoder.maintenance()) {
+                    Some(text) => text,
+                    None => return Ok(()),
+                }
+            }
+            Command::Close => unreachable!("Close is handled by the read loop"),
+        };
+
+        self.socket.send(Message::Text(text)).await?;
+
+        Ok(())
+    }
+
+    /// Returns false if the connection should stop, because the event stream was dropped or the
+    /// policy gave up.
+    async fn reconnect(&mut self, error: Error, policy: &ReconnectPolicy) -> bool {
+        self.decoder.reset();
+        self.orders.reset();
+
+        let mut events = vec![Ok(WebSocketEvent::Disconnected(error))];
+        events.extend(
+            self.session
+                .books()
+                .map(|book| Ok(WebSocketEvent::BookReset(book.clone()))),
+        );
+
+        for event in events {
+            if self.events.send(event).await.is_err() {
+                return false;
+            }
+        }
+
+        let mut attempt = 1;
+
+        loop {
+            tokio::time::sleep(policy.backoff(attempt)).await;
+
+            match self.restore().await {
+                Ok(socket) => {
+                    self.socket = socket;
+                    self.watchdog.reset();
+                    break;
+                }
+                Err(e) if !is_connection_error(&e) || !policy.allows(attempt + 1) => {
+                    let _ = self.events.send(Err(e)).await;
+                    return false;
+                }
+                Err(_) => attempt += 1,
+            }
+        }
+
+        self.events
+            .send(Ok(WebSocketEvent::Reconnected))
+            .await
+            .is_ok()
+    }
+
+    // opens a new connection, authenticated and subscribed like the lost one
+    async fn restore(&self) -> Result<AsyncSocket, Error> {
+        let mut socket = connect_websocket_async(&self.config).await?;
+        let auth_message = match self.session.auth() {
+            Some(auth) => Some(
+                auth.message_async(self.config.nonce_provider().as_ref())
+                    .await?,
+            ),
+            None => None,
+        };
+
+        for text in self.session.restore(auth_message) {
+            socket.send(Message::Text(text)).await?;
+        }
+
+        Ok(socket)
+    }
+}
+
+/// Sends commands to an async websocket connection. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct WebSocketHandle {
+    tx: mpsc::Sender<Command>,
+    config: ClientConfig,
+}
+
+impl WebSocketHandle {
+    /// Sends a raw text frame.
+    pub async fn send<S: Into<String>>(&self, raw: S) -> Result<(), Error> {
+        self.command(Command::Send(raw.into())).await
+    }
+
+    /// Closes the connection. The event stream ends once the close is sent.
+    pub async fn close(&self) -> Result<(), Error> {
+        self.command(Command::Close).await
+    }
+
+    /// Whether the connection stopped, after which every command fails.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Authenticates the connection.
+    ///
+    /// See [`crate::websockets::WebSockets::auth`].
+    pub async fn auth<S>(
+        &self,
+        api_key: S,
+        api_secret: S,
+        dms: bool,
+        filters: &[&str],
+    ) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+    {
+        let signer = HmacSigner::new(api_key.as_ref(), api_secret.as_ref());
+
+        self.auth_with_signer(Arc::new(signer), dms, filters).await
+    }
+
+    /// Authenticates the connection, delegating the signature to `signer`.
+    pub async fn auth_with_signer(
+        &self,
+        signer: Arc<dyn Signer>,
+        dms: bool,
+        filters: &[&str],
+    ) -> Result<(), Error> {
+        let auth = Auth::new(signer, dms, filters);
+        let msg = auth
+            .message_async(self.config.nonce_provider().as_ref())
+            .await?;
+
+        self.command(Command::Auth(auth, msg)).await
+    }
+
+    /// Enables the options of `flags` on the connection.
+    ///
+    /// See [`crate::websockets::WebSockets::configure`].
+    pub async fn configure(&self, flags: ConfFlags) -> Result<(), Error> {
+        self.command(Command::Configure(flags)).await
+    }
+
+    pub async fn subscribe_ticker<S>(&self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::Ticker { symbol }).await
+    }
+
+    pub async fn subscribe_trades<S>(&self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::Trades { symbol }).await
+    }
+
+    /// Subscribes to the candles of `key`.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_candles`].
+    pub async fn subscribe_candles(&self, key: CandleKey) -> Result<(), Error> {
+        self.subscribe(Subscription::Candles {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Subscribes to a status channel.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_status`].
+    pub async fn subscribe_status<S>(&self, key: S) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        self.subscribe(Subscription::Status { key: key.into() })
+            .await
+    }
+
+    /// Subscribes to a book, failing on an invalid combination of parameters.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_books`].
+    pub async fn subscribe_books<S>(
+        &self,
+        symbol: S,
+        et: EventType,
+        prec: BookPrecision,
+        freq: BookFrequency,
+        len: BookLength,
+    ) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let subscription = Subscription::books(et.format_symbol(symbol.into()), prec, freq, len)?;
+
+        self.subscribe(subscription).await
+    }
+
+    pub async fn subscribe_raw_books<S>(&self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::RawBooks { symbol }).await
+    }
+
+    pub(crate) async fn subscribe(&self, subscription: Subscription) -> Result<(), Error> {
+        self.command(Command::Subscribe(subscription)).await
+    }
+
+    /// Unsubscribes from the channel with the given id.
+    ///
+    /// See [`crate::websockets::WebSockets::unsubscribe`].
+    pub async fn unsubscribe(&self, chan_id: u32) -> Result<(), Error> {
+        self.command(Command::Unsubscribe(Unsubscribe::Channel(chan_id)))
+            .await
+    }
+
+    /// Unsubscribes from the channel opened by `subscription`. If its subscription was not
+    /// confirmed yet, an [`Error::Protocol`] is yielded by the event stream.
+    pub async fn unsubscribe_from(&self, subscription: Subscription) -> Result<(), Error> {
+        self.command(Command::Unsubscribe(Unsubscribe::Subscription(
+            subscription,
+        )))
+        .await
+    }
+
+    /// Submits an order on the authenticated connection.
+    ///
+    /// See [`crate::websockets::WebSockets::submit_order`].
+    pub async fn submit_order(&self, order: &OrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::submit(order)?).await
+    }
+
+    pub async fn update_order(&self, order: &UpdateOrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::update(order)?).await
+    }
+
+    pub async fn cancel_order(&self, order: &CancelOrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::cancel(order)?).await
+    }
+
+    pub async fn cancel_orders_multi(
+        &self,
+        orders: &CancelOrdersForm,
+    ) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::cancel_multi(orders)?).await
+    }
+
+    pub async fn order_multi_op(
+        &self,
+        operations: &[OrderOperation],
+    ) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::multi_op(operations)?).await
+    }
+
+    async fn order(&self, message: OrderMessage) -> Result<OrderRequest, Error> {
+        let (text, pending, rx) = message.into_parts();
+
+        self.command(Command::Order(text, pending)).await?;
+
+        Ok(OrderRequest::new(rx))
+    }
+
+    async fn command(&self, command: Command) -> Result<(), Error> {
+        self.tx
+            .send(command)
+            .await
+            .map_err(|_| Error::Disconnected("Connection closed".into()))
+    }
+}
+
+/// The messages received on an async websocket connection.
+///
+/// Decoding errors are yielded without ending the stream. The stream ends after the connection
+/// is closed, the last item being the error that closed it, if any.
+#[derive(Debug)]
+pub struct EventStream {
+    rx: mpsc::Receiver<Result<WebSocketEvent, Error>>,
+}
+
+impl Stream for EventStream {
+    type Item = Result<WebSocketEvent, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
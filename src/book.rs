@@ -1,10 +1,13 @@
-use serde_json::from_str;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
 use crate::client::Client;
 use crate::endpoints::PublicEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BookPrecision {
     #[serde(rename = "P0")]
     P0,
@@ -20,15 +23,104 @@ pub enum BookPrecision {
     R0,
 }
 
-impl ToString for BookPrecision {
-    fn to_string(&self) -> String {
+impl fmt::Display for BookPrecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookPrecision::P0 => write!(f, "P0"),
+            BookPrecision::P1 => write!(f, "P1"),
+            BookPrecision::P2 => write!(f, "P2"),
+            BookPrecision::P3 => write!(f, "P3"),
+            BookPrecision::P4 => write!(f, "P4"),
+            BookPrecision::R0 => write!(f, "R0"),
+        }
+    }
+}
+
+impl FromStr for BookPrecision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "P0" => Ok(BookPrecision::P0),
+            "P1" => Ok(BookPrecision::P1),
+            "P2" => Ok(BookPrecision::P2),
+            "P3" => Ok(BookPrecision::P3),
+            "P4" => Ok(BookPrecision::P4),
+            "R0" => Ok(BookPrecision::R0),
+            _ => Err(Error::InvalidInput(format!("Invalid book precision {}", s))),
+        }
+    }
+}
+
+/// How often the updates of a book are sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookFrequency {
+    /// Realtime
+    #[serde(rename = "F0")]
+    F0,
+    /// Every 2 seconds
+    #[serde(rename = "F1")]
+    F1,
+}
+
+impl fmt::Display for BookFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookFrequency::F0 => write!(f, "F0"),
+            BookFrequency::F1 => write!(f, "F1"),
+        }
+    }
+}
+
+impl FromStr for BookFrequency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "F0" => Ok(BookFrequency::F0),
+            "F1" => Ok(BookFrequency::F1),
+            _ => Err(Error::InvalidInput(format!("Invalid book frequency {}", s))),
+        }
+    }
+}
+
+/// The number of price levels of a book, on each side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookLength {
+    L1,
+    L25,
+    L100,
+    L250,
+}
+
+impl BookLength {
+    /// The number of price levels.
+    pub fn levels(self) -> u32 {
         match self {
-            BookPrecision::P0 => "P0".into(),
-            BookPrecision::P1 => "P1".into(),
-            BookPrecision::P2 => "P2".into(),
-            BookPrecision::P3 => "P3".into(),
-            BookPrecision::P4 => "P4".into(),
-            BookPrecision::R0 => "R0".into(),
+            BookLength::L1 => 1,
+            BookLength::L25 => 25,
+            BookLength::L100 => 100,
+            BookLength::L250 => 250,
+        }
+    }
+}
+
+impl fmt::Display for BookLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.levels())
+    }
+}
+
+impl TryFrom<u32> for BookLength {
+    type Error = Error;
+
+    fn try_from(len: u32) -> Result<Self, Self::Error> {
+        match len {
+            1 => Ok(BookLength::L1),
+            25 => Ok(BookLength::L25),
+            100 => Ok(BookLength::L100),
+            250 => Ok(BookLength::L250),
+            _ => Err(Error::InvalidInput(format!("Invalid book length {}", len))),
         }
     }
 }
@@ -70,7 +162,7 @@ impl Book {
         &self,
         symbol: S,
         precision: BookPrecision,
-    ) -> Result<Vec<FundingCurrency>, BoxError>
+    ) -> Result<Vec<FundingCurrency>, Error>
     where
         S: Into<String>,
     {
@@ -80,14 +172,14 @@ impl Book {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
     pub async fn trading_pair<S>(
         &self,
         symbol: S,
         precision: BookPrecision,
-    ) -> Result<Vec<TradingPair>, BoxError>
+    ) -> Result<Vec<TradingPair>, Error>
     where
         S: Into<String>,
     {
@@ -97,6 +189,215 @@ impl Book {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
+    }
+}
+
+static CHECKSUM_DEPTH: usize = 25;
+
+#[derive(Debug)]
+struct Level {
+    // the price, or the order id for raw books
+    key: f64,
+    price: f64,
+    amount: f64,
+}
+
+/// A copy of a trading book, maintained from its snapshot and updates to verify its checksums.
+#[derive(Debug, Default)]
+pub(crate) struct LocalBook {
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl LocalBook {
+    /// Applies a price level. A level with no orders is removed.
+    pub(crate) fn update_level(&mut self, level: &TradingPair) {
+        self.remove(level.price);
+
+        if level.count > 0 {
+            self.insert(level.price, level.price, level.amount);
+        }
+    }
+
+    /// Applies an order. An order with no price is removed.
+    pub(crate) fn update_order(&mut self, order: &RawBook) {
+        let key = order.order_id as f64;
+        self.remove(key);
+
+        if order.price != 0.0 {
+            self.insert(key, order.price, order.amount);
+        }
+    }
+
+    /// The CRC32 of the top 25 bids and asks, interleaved, as computed by Bitfinex.
+    pub(crate) fn checksum(&self) -> i32 {
+        let mut bids: Vec<&Level> = self.bids.iter().collect();
+        let mut asks: Vec<&Level> = self.asks.iter().collect();
+
+        bids.sort_by(|a, b| compare(b.price, a.price).then(compare(a.key, b.key)));
+        asks.sort_by(|a, b| compare(a.price, b.price).then(compare(a.key, b.key)));
+
+        let mut values = Vec::with_capacity(CHECKSUM_DEPTH * 4);
+
+        for i in 0..CHECKSUM_DEPTH {
+            for level in bids.get(i).into_iter().chain(asks.get(i)) {
+                values.push(format_number(level.key));
+                values.push(format_number(level.amount));
+            }
+        }
+
+        crc32fast::hash(values.join(":").as_bytes()) as i32
+    }
+
+    fn insert(&mut self, key: f64, price: f64, amount: f64) {
+        let level = Level { key, price, amount };
+
+        if amount > 0.0 {
+            self.bids.push(level);
+        } else {
+            self.asks.push(level);
+        }
+    }
+
+    fn remove(&mut self, key: f64) {
+        self.bids.retain(|level| level.key != key);
+        self.asks.retain(|level| level.key != key);
+    }
+}
+
+fn compare(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+// the checksum is computed over the numbers as formatted by javascript, which switches to the
+// exponent notation below 1e-6 and from 1e21
+fn format_number(n: f64) -> String {
+    let abs = n.abs();
+
+    if abs == 0.0 || (1e-6..1e21).contains(&abs) {
+        format!("{}", n)
+    } else if abs >= 1.0 {
+        format!("{:e}", n).replacen('e', "e+", 1)
+    } else {
+        format!("{:e}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trading_book(levels: &[(f64, i64, f64)]) -> LocalBook {
+        let mut book = LocalBook::default();
+
+        for &(price, count, amount) in levels {
+            book.update_level(&TradingPair {
+                price,
+                count,
+                amount,
+            });
+        }
+
+        book
+    }
+
+    // the expected values are the CRC32 of the interleaved levels, e.g. for the first snapshot
+    // "7254.7:0.3:7255.1:-0.4:7254.6:5e-8:7255.2:-2.5:7254:12:7256:-1e-7"
+    #[test]
+    fn checksum_of_trading_book() {
+        let mut book = trading_book(&[
+            (7254.7, 3, 0.3),
+            (7254.6, 1, 0.00000005),
+            (7254.0, 2, 12.0),
+            (7255.1, 1, -0.4),
+            (7255.2, 4, -2.5),
+            (7256.0, 1, -0.0000001),
+        ]);
+        assert_eq!(book.checksum(), 2013500012);
+
+        book.update_level(&TradingPair {
+            price: 7254.6,
+            count: 0,
+            amount: 1.0,
+        });
+        assert_eq!(book.checksum(), 1704169902);
+    }
+
+    #[test]
+    fn checksum_covers_top_levels_only() {
+        let levels: Vec<_> = (100..130).map(|price| (price as f64, 1, 1.0)).collect();
+
+        assert_eq!(trading_book(&levels).checksum(), -2036853776);
+    }
+
+    #[test]
+    fn checksum_of_raw_book() {
+        let mut book = LocalBook::default();
+
+        for &(order_id, price, amount) in &[
+            (1002, 7254.7, 0.2),
+            (2001, 7255.1, -0.4),
+            (1001, 7254.7, 0.3),
+        ] {
+            book.update_order(&RawBook {
+                order_id,
+                price,
+                amount,
+            });
+        }
+
+        // "1001:0.3:2001:-0.4:1002:0.2"
+        assert_eq!(book.checksum(), -677891357);
+    }
+
+    #[test]
+    fn book_parameters_are_parsed_from_their_names() {
+        for prec in &["P0", "P1", "P2", "P3", "P4", "R0"] {
+            assert_eq!(prec.parse::<BookPrecision>().unwrap().to_string(), *prec);
+        }
+        for freq in &["F0", "F1"] {
+            assert_eq!(freq.parse::<BookFrequency>().unwrap().to_string(), *freq);
+        }
+        for &len in &[1, 25, 100, 250] {
+            assert_eq!(BookLength::try_from(len).unwrap().levels(), len);
+        }
+    }
+
+    #[test]
+    fn invalid_book_parameters_are_rejected() {
+        for prec in &["P5", "p0", "R1", ""] {
+            assert!(matches!(
+                prec.parse::<BookPrecision>(),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        for freq in &["F2", "f0", ""] {
+            assert!(matches!(
+                freq.parse::<BookFrequency>(),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        for &len in &[0, 10, 50, 251] {
+            assert!(matches!(
+                BookLength::try_from(len),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn format_number_matches_javascript() {
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(7254.0), "7254");
+        assert_eq!(format_number(-12.0), "-12");
+        assert_eq!(format_number(7254.7), "7254.7");
+        assert_eq!(format_number(0.000001), "0.000001");
+        assert_eq!(format_number(0.0000001), "1e-7");
+        assert_eq!(format_number(-0.00000005), "-5e-8");
+        assert_eq!(format_number(1.5e-10), "1.5e-10");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_number(1e21), "1e+21");
+        assert_eq!(format_number(-2.5e22), "-2.5e+22");
     }
 }
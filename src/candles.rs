@@ -1,10 +1,10 @@
-use serde_json::from_str;
+use std::fmt;
 
 use crate::client::Client;
 use crate::endpoints::PublicEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CandlesTimeFrame {
     #[serde(rename = "1m")]
     OneMinute,
@@ -30,20 +30,114 @@ pub enum CandlesTimeFrame {
     TwoWeeks,
 }
 
-impl ToString for CandlesTimeFrame {
-    fn to_string(&self) -> String {
+impl fmt::Display for CandlesTimeFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CandlesTimeFrame::OneMinute => "1m".into(),
-            CandlesTimeFrame::FiveMinutes => "5m".into(),
-            CandlesTimeFrame::FifteenMinutes => "15m".into(),
-            CandlesTimeFrame::ThirtyMinutes => "30m".into(),
-            CandlesTimeFrame::OneHour => "1h".into(),
-            CandlesTimeFrame::ThreeHours => "3h".into(),
-            CandlesTimeFrame::SixHours => "6h".into(),
-            CandlesTimeFrame::TwelveHours => "12h".into(),
-            CandlesTimeFrame::OneDay => "1D".into(),
-            CandlesTimeFrame::OneWeek => "7D".into(),
-            CandlesTimeFrame::TwoWeeks => "14D".into(),
+            CandlesTimeFrame::OneMinute => write!(f, "1m"),
+            CandlesTimeFrame::FiveMinutes => write!(f, "5m"),
+            CandlesTimeFrame::FifteenMinutes => write!(f, "15m"),
+            CandlesTimeFrame::ThirtyMinutes => write!(f, "30m"),
+            CandlesTimeFrame::OneHour => write!(f, "1h"),
+            CandlesTimeFrame::ThreeHours => write!(f, "3h"),
+            CandlesTimeFrame::SixHours => write!(f, "6h"),
+            CandlesTimeFrame::TwelveHours => write!(f, "12h"),
+            CandlesTimeFrame::OneDay => write!(f, "1D"),
+            CandlesTimeFrame::OneWeek => write!(f, "7D"),
+            CandlesTimeFrame::TwoWeeks => write!(f, "14D"),
+        }
+    }
+}
+
+/// The key of a candles channel or endpoint, e.g. `trade:1m:tBTCUSD` or `trade:1m:fUSD:p30`.
+///
+/// ```
+/// use bitfinex::candles::{CandleKey, CandlesTimeFrame};
+///
+/// let key = CandleKey::funding("USD", CandlesTimeFrame::OneMinute, 30);
+/// assert_eq!(key.to_string(), "trade:1m:fUSD:p30");
+///
+/// let key = CandleKey::funding_aggregated("USD", CandlesTimeFrame::OneMinute, 30, 2, 30);
+/// assert_eq!(key.to_string(), "trade:1m:fUSD:a30:p2:p30");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandleKey {
+    timeframe: CandlesTimeFrame,
+    symbol: String,
+    period: Option<String>,
+}
+
+impl CandleKey {
+    /// A key for `symbol` as is, e.g. `tBTCF0:USTF0` for a derivatives pair.
+    pub fn new<S>(symbol: S, timeframe: CandlesTimeFrame) -> Self
+    where
+        S: Into<String>,
+    {
+        CandleKey {
+            timeframe,
+            symbol: symbol.into(),
+            period: None,
+        }
+    }
+
+    /// The candles of a trading pair, e.g. `BTCUSD`.
+    pub fn trading<S>(symbol: S, timeframe: CandlesTimeFrame) -> Self
+    where
+        S: Into<String>,
+    {
+        CandleKey::new(format!("t{}", symbol.into()), timeframe)
+    }
+
+    /// The candles of the funding offers of a currency, e.g. `USD`, for a period of `period` days.
+    pub fn funding<S>(symbol: S, timeframe: CandlesTimeFrame, period: u32) -> Self
+    where
+        S: Into<String>,
+    {
+        CandleKey::new(format!("f{}", symbol.into()), timeframe).with_period(format!("p{}", period))
+    }
+
+    /// The candles of the funding offers of a currency aggregated over `aggregation` days, for
+    /// the periods from `min_period` to `max_period` days.
+    pub fn funding_aggregated<S>(
+        symbol: S,
+        timeframe: CandlesTimeFrame,
+        aggregation: u32,
+        min_period: u32,
+        max_period: u32,
+    ) -> Self
+    where
+        S: Into<String>,
+    {
+        CandleKey::new(format!("f{}", symbol.into()), timeframe)
+            .with_period(format!("a{}:p{}:p{}", aggregation, min_period, max_period))
+    }
+
+    /// Appends a period or aggregation to the key, e.g. `p30` or `a30:p2:p30`.
+    pub fn with_period<S>(mut self, period: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.period = Some(period.into());
+        self
+    }
+
+    pub fn timeframe(&self) -> CandlesTimeFrame {
+        self.timeframe
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn period(&self) -> Option<&str> {
+        self.period.as_deref()
+    }
+}
+
+impl fmt::Display for CandleKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.period {
+            Some(ref period) => write!(f, "trade:{}:{}:{}", self.timeframe, self.symbol, period),
+            None => write!(f, "trade:{}:{}", self.timeframe, self.symbol),
         }
     }
 }
@@ -56,11 +150,11 @@ pub enum CandlesSection {
     Hist,
 }
 
-impl ToString for CandlesSection {
-    fn to_string(&self) -> String {
+impl fmt::Display for CandlesSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CandlesSection::Last => "last".into(),
-            CandlesSection::Hist => "hist".into(),
+            CandlesSection::Last => write!(f, "last"),
+            CandlesSection::Hist => write!(f, "hist"),
         }
     }
 }
@@ -100,9 +194,7 @@ impl CandleHistoryParams {
             "end",
             self.end.map(|a| a.to_string()).unwrap_or("".into()),
             "sort",
-            self.sort
-                .map(|a| if a { "1" } else { "0" })
-                .unwrap_or("".into()),
+            self.sort.map(|a| if a { "1" } else { "0" }).unwrap_or(""),
         )
     }
 }
@@ -122,43 +214,93 @@ pub struct Candles {
     client: Client,
 }
 
-// TODO: funding missing
 impl Candles {
     pub fn new(client: Client) -> Self {
         Candles { client }
     }
 
-    pub async fn last<S>(&self, symbol: S, timeframe: CandlesTimeFrame) -> Result<Candle, BoxError>
+    pub async fn last<S>(&self, symbol: S, timeframe: CandlesTimeFrame) -> Result<Candle, Error>
     where
         S: Into<String>,
     {
+        self.last_by_key(CandleKey::new(symbol, timeframe)).await
+    }
+
+    /// The last candle of any key, e.g. of funding candles.
+    pub async fn last_by_key(&self, key: CandleKey) -> Result<Candle, Error> {
         let endpoint = PublicEndpoint::Candles {
-            symbol: symbol.into(),
-            timeframe,
+            key,
             section: CandlesSection::Last,
-            funding_period: None,
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
     pub async fn history<S>(
         &self,
         symbol: S,
         timeframe: CandlesTimeFrame,
-    ) -> Result<Vec<Candle>, BoxError>
+    ) -> Result<Vec<Candle>, Error>
     where
         S: Into<String>,
     {
+        self.history_by_key(CandleKey::new(symbol, timeframe)).await
+    }
+
+    /// The candles history of any key, e.g. of funding candles.
+    pub async fn history_by_key(&self, key: CandleKey) -> Result<Vec<Candle>, Error> {
         let endpoint = PublicEndpoint::Candles {
-            symbol: symbol.into(),
-            timeframe,
+            key,
             section: CandlesSection::Hist,
-            funding_period: None,
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trading_keys_are_prefixed() {
+        let key = CandleKey::trading("BTCUSD", CandlesTimeFrame::OneHour);
+
+        assert_eq!(key.symbol(), "tBTCUSD");
+        assert_eq!(key.period(), None);
+        assert_eq!(key.to_string(), "trade:1h:tBTCUSD");
+    }
+
+    #[test]
+    fn funding_keys_have_a_period() {
+        let key = CandleKey::funding("USD", CandlesTimeFrame::OneMinute, 30);
+
+        assert_eq!(key.timeframe(), CandlesTimeFrame::OneMinute);
+        assert_eq!(key.symbol(), "fUSD");
+        assert_eq!(key.period(), Some("p30"));
+        assert_eq!(key.to_string(), "trade:1m:fUSD:p30");
+    }
+
+    #[test]
+    fn aggregated_funding_keys_have_a_period_range() {
+        let key = CandleKey::funding_aggregated("USD", CandlesTimeFrame::OneDay, 10, 2, 30);
+
+        assert_eq!(key.symbol(), "fUSD");
+        assert_eq!(key.period(), Some("a10:p2:p30"));
+        assert_eq!(key.to_string(), "trade:1D:fUSD:a10:p2:p30");
+    }
+
+    #[test]
+    fn with_period_replaces_the_period() {
+        let key = CandleKey::new("fUST", CandlesTimeFrame::FiveMinutes).with_period("p2");
+        assert_eq!(key.to_string(), "trade:5m:fUST:p2");
+
+        let key = CandleKey::funding("USD", CandlesTimeFrame::FiveMinutes, 30).with_period("p7");
+        assert_eq!(
+            key,
+            CandleKey::funding("USD", CandlesTimeFrame::FiveMinutes, 7)
+        );
     }
 }
@@ -0,0 +1,401 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::errors::{from_json, Error};
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport, TransportFuture};
+
+/// A line of a cassette file.
+///
+/// Request headers are not recorded, so cassettes never contain API keys or signatures.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CassetteEntry {
+    /// A REST request sent by the client
+    Request {
+        timestamp: u64,
+        method: String,
+        url: String,
+        body: Option<String>,
+    },
+    /// The response to the preceding request
+    Response {
+        timestamp: u64,
+        status: u16,
+        body: String,
+    },
+    /// A text frame received on the websocket
+    Frame { timestamp: u64, data: String },
+}
+
+impl CassetteEntry {
+    /// Milliseconds since the epoch at which the entry was recorded.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            CassetteEntry::Request { timestamp, .. }
+            | CassetteEntry::Response { timestamp, .. }
+            | CassetteEntry::Frame { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// cassettes may be recorded against a different host than the one used to replay them
+fn relative_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        },
+        Err(_) => url.to_owned(),
+    }
+}
+
+/// Appends entries to a JSONL cassette file. Clones write to the same file.
+#[derive(Clone, Debug)]
+pub struct CassetteRecorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl CassetteRecorder {
+    /// Creates the cassette, or appends to it if it already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(CassetteRecorder {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn record(&self, entry: &CassetteEntry) -> Result<(), Error> {
+        self.record_all(std::slice::from_ref(entry))
+    }
+
+    /// Records several entries, without interleaving them with entries recorded by clones.
+    pub fn record_all(&self, entries: &[CassetteEntry]) -> Result<(), Error> {
+        let mut lines = String::new();
+
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        self.file.lock().unwrap().write_all(lines.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub(crate) fn record_frame(&self, data: &str) -> Result<(), Error> {
+        self.record(&CassetteEntry::Frame {
+            timestamp: now_millis(),
+            data: data.to_owned(),
+        })
+    }
+}
+
+/// The entries of a recorded session.
+#[derive(Clone, Debug, Default)]
+pub struct Cassette {
+    entries: Vec<CassetteEntry>,
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if !line.trim().is_empty() {
+                entries.push(from_json(&line)?);
+            }
+        }
+
+        Ok(Cassette { entries })
+    }
+
+    pub fn entries(&self) -> &[CassetteEntry] {
+        &self.entries
+    }
+
+    /// The websocket frames, in the order they were received.
+    pub fn frames(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|e| match e {
+            CassetteEntry::Frame { data, .. } => Some(data.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Records every request and response going through the wrapped transport.
+///
+/// A request is written together with its response, so concurrent requests never get mixed up.
+/// Request headers are not recorded: the API key, nonce and signature of authenticated requests
+/// never end up in the cassette.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn HttpTransport>,
+    recorder: CassetteRecorder,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, recorder: CassetteRecorder) -> Self {
+        RecordingTransport { inner, recorder }
+    }
+}
+
+impl HttpTransport for RecordingTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let request_entry = CassetteEntry::Request {
+                timestamp: now_millis(),
+                method: request.method.to_string(),
+                url: request.url.clone(),
+                body: request.body.clone(),
+            };
+
+            let response = self.inner.send(request).await?;
+
+            self.recorder.record_all(&[
+                request_entry,
+                CassetteEntry::Response {
+                    timestamp: now_millis(),
+                    status: response.status.as_u16(),
+                    body: response.body.clone(),
+                },
+            ])?;
+
+            Ok(response)
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Exchange {
+    method: String,
+    url: String,
+    body: Option<String>,
+    response: HttpResponse,
+    replayed: bool,
+}
+
+/// Answers requests with the responses of a cassette, without any network access.
+///
+/// Each recorded response is served once, to the first request with the same method, path,
+/// query and body. Hosts and headers are ignored, since cassettes do not record headers.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: &Cassette) -> Self {
+        let mut exchanges = Vec::new();
+        let mut pending = None;
+
+        for entry in cassette.entries() {
+            match entry {
+                CassetteEntry::Request {
+                    method, url, body, ..
+                } => pending = Some((method.clone(), relative_url(url), body.clone())),
+                CassetteEntry::Response { status, body, .. } => {
+                    if let Some((method, url, request_body)) = pending.take() {
+                        exchanges.push(Exchange {
+                            method,
+                            url,
+                            body: request_body,
+                            response: HttpResponse {
+                                status: StatusCode::from_u16(*status)
+                                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                                body: body.clone(),
+                            },
+                            replayed: false,
+                        });
+                    }
+                }
+                CassetteEntry::Frame { .. } => {}
+            }
+        }
+
+        ReplayTransport {
+            exchanges: Mutex::new(exchanges),
+        }
+    }
+}
+
+impl HttpTransport for ReplayTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        let method = request.method.to_string();
+        let url = relative_url(&request.url);
+
+        let response = self
+            .exchanges
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|e| !e.replayed && e.method == method && e.url == url && e.body == request.body)
+            .map(|e| {
+                e.replayed = true;
+                e.response.clone()
+            });
+
+        Box::pin(async move {
+            response.ok_or_else(|| {
+                Error::InvalidInput(format!("No recorded response for {} {}", method, url))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::Method;
+
+    use super::*;
+    use crate::events::{DataEvent, NotificationEvent};
+    use crate::transport::MockTransport;
+    use crate::websockets::{EventHandler, WebSockets};
+
+    static TICKER: &str = "/v2/ticker/tBTCUSD";
+    static WALLETS: &str = "/v2/auth/r/wallets";
+
+    fn cassette_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "bitfinex-cassette-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn request(method: Method, path: &str, body: Option<&str>) -> HttpRequest {
+        let mut headers = HeaderMap::new();
+        headers.insert("bfx-signature", HeaderValue::from_static("deadbeef"));
+
+        HttpRequest {
+            method,
+            url: format!("https://api.bitfinex.com{}", path),
+            headers,
+            body: body.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_each_recorded_response_once() {
+        let path = cassette_path("round-trip");
+        let mock = MockTransport::new()
+            .with_response(TICKER, None, "[1]")
+            .with_response(TICKER, None, "[2]")
+            .with_response(WALLETS, Some("{}"), "[]");
+        let recording =
+            RecordingTransport::new(Arc::new(mock), CassetteRecorder::create(&path).unwrap());
+
+        let exchanges = vec![
+            (Method::GET, TICKER, None, "[1]"),
+            (Method::GET, TICKER, None, "[2]"),
+            (Method::POST, WALLETS, Some("{}"), "[]"),
+        ];
+
+        for (method, url, body, _) in exchanges.iter().cloned() {
+            recording.send(request(method, url, body)).await.unwrap();
+        }
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(!recorded.contains("deadbeef"));
+
+        let cassette = Cassette::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cassette.entries().len(), 6);
+
+        let replay = ReplayTransport::new(&cassette);
+
+        for (method, url, body, expected) in exchanges {
+            let response = replay.send(request(method, url, body)).await.unwrap();
+            assert_eq!(response.body, expected);
+        }
+
+        match replay.send(request(Method::GET, TICKER, None)).await {
+            Err(Error::InvalidInput(msg)) => assert!(msg.contains(TICKER)),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[derive(Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl Events {
+        fn push<E: Debug>(&self, event: E) {
+            self.0.lock().unwrap().push(format!("{:?}", event));
+        }
+    }
+
+    impl EventHandler for Events {
+        fn on_connect(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_auth(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_subscribed(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_data_event(&mut self, event: DataEvent) {
+            self.push(event);
+        }
+
+        fn on_error(&mut self, error: Error) {
+            self.push(error);
+        }
+    }
+
+    #[test]
+    fn replays_recorded_frames() {
+        let path = cassette_path("frames");
+        let recorder = CassetteRecorder::create(&path).unwrap();
+        let frames = [
+            r#"{"event":"info","version":2,"platform":{"status":1}}"#,
+            r#"{"event":"subscribed","channel":"trades","chanId":17,"symbol":"tBTCUSD","pair":"BTCUSD"}"#,
+            r#"[17,[[401597395,1574694475039,0.005,7244.9]]]"#,
+            r#"[17,"te",[401597393,1574694478808,0.005,7245.3]]"#,
+        ];
+
+        for frame in &frames {
+            recorder.record_frame(frame).unwrap();
+        }
+
+        let cassette = Cassette::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cassette.frames().collect::<Vec<_>>(), frames);
+
+        let events = Events::default();
+        let received = events.0.clone();
+        let mut web_socket = WebSockets::new();
+        web_socket.add_event_handler(events);
+        web_socket.replay(&cassette).unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 4, "{:?}", received);
+        assert!(received[1].starts_with("TradingSubscribed"));
+        assert!(received[2].starts_with("TradesTradingSnapshotEvent(17,"));
+        assert!(received[3].starts_with("TradesTradingUpdateEvent(17, \"te\""));
+    }
+}
@@ -1,41 +1,87 @@
-use reqwest;
+use std::future::Future;
+use std::sync::Arc;
+
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, USER_AGENT};
-use reqwest::{Response, StatusCode};
+use reqwest::{Method, StatusCode};
 use serde::Serialize;
 
-use crate::auth;
+use crate::config::ClientConfig;
 use crate::endpoints::{AuthenticatedEndpoint, PublicEndpoint};
-use crate::errors::BoxError;
+use crate::errors::{ApiError, Error};
+use crate::ratelimit::RateLimiter;
+use crate::signer::{sign_async, HmacSigner, Signer};
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport, ReqwestTransport};
 
-static NO_PARAMS: &'static [(); 0] = &[];
+static NO_PARAMS: &[(); 0] = &[];
 
 #[derive(Clone, Debug)]
 pub struct Client {
-    api_key: String,
-    secret_key: String,
-    client: reqwest::Client,
+    signer: Arc<dyn Signer>,
+    limiter: Arc<RateLimiter>,
+    transport: Arc<dyn HttpTransport>,
+    user_agent: HeaderValue,
+    config: ClientConfig,
 }
 
 impl Client {
-    pub fn new(api_key: Option<String>, secret_key: Option<String>) -> Self {
-        Client {
-            api_key: api_key.unwrap_or("".into()),
-            secret_key: secret_key.unwrap_or("".into()),
-            client: reqwest::Client::new(),
-        }
+    pub fn new(api_key: Option<String>, secret_key: Option<String>) -> Result<Self, Error> {
+        Client::with_config(api_key, secret_key, ClientConfig::default())
+    }
+
+    pub fn with_config(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        config: ClientConfig,
+    ) -> Result<Self, Error> {
+        let signer = HmacSigner::new(api_key.unwrap_or_default(), secret_key.unwrap_or_default());
+
+        Client::with_signer(Arc::new(signer), config)
+    }
+
+    pub fn with_signer(signer: Arc<dyn Signer>, config: ClientConfig) -> Result<Self, Error> {
+        let transport = match config.transport() {
+            Some(transport) => transport.clone(),
+            None => Arc::new(ReqwestTransport::from_config(&config)?),
+        };
+        let user_agent = HeaderValue::from_str(&config.user_agent()).map_err(|_| {
+            Error::InvalidInput(format!("Invalid user agent {}", config.user_agent()))
+        })?;
+
+        Ok(Client {
+            signer,
+            limiter: Arc::new(RateLimiter::new(config.rate_limit_policy())),
+            transport,
+            user_agent,
+            config,
+        })
     }
 
-    pub async fn get(&self, endpoint: PublicEndpoint) -> Result<String, BoxError> {
-        let response = self.client.get(&endpoint.to_string()).send().await?;
+    pub async fn get(&self, endpoint: PublicEndpoint) -> Result<String, Error> {
+        let url = format!("{}{}", self.config.public_host(), endpoint);
+        let limit = (endpoint.rate_limit_key(), endpoint.rate_limit());
+
+        self.with_retry(true, limit, || async {
+            let mut headers = HeaderMap::new();
+            headers.insert(USER_AGENT, self.user_agent.clone());
 
-        self.handler(response).await
+            let request = HttpRequest {
+                method: Method::GET,
+                url: url.clone(),
+                headers,
+                body: None,
+            };
+            let response = self.transport.send(request).await?;
+
+            self.handler(response)
+        })
+        .await
     }
 
     pub async fn post_signed(
         &self,
         endpoint: &AuthenticatedEndpoint,
         payload: String,
-    ) -> Result<String, BoxError> {
+    ) -> Result<String, Error> {
         self.post_signed_params(endpoint, payload, NO_PARAMS).await
     }
 
@@ -44,43 +90,87 @@ impl Client {
         endpoint: &AuthenticatedEndpoint,
         payload: String,
         params: &P,
-    ) -> Result<String, BoxError> {
-        let response = self
-            .client
-            .post(&endpoint.to_string())
-            .body(payload.clone())
-            .query(params)
-            .headers(self.build_headers(&endpoint, payload).await?)
-            .send()
-            .await?;
+    ) -> Result<String, Error> {
+        let query =
+            serde_urlencoded::to_string(params).map_err(|e| Error::InvalidInput(e.to_string()))?;
+        let url = match query.as_str() {
+            "" => format!("{}{}", self.config.authenticated_host(), endpoint),
+            query => format!("{}{}?{}", self.config.authenticated_host(), endpoint, query),
+        };
+        let limit = (endpoint.rate_limit_key(), endpoint.rate_limit());
 
-        self.handler(response).await
+        // headers are rebuilt on every attempt, as each one needs a fresh nonce
+        self.with_retry(endpoint.is_idempotent(), limit, || async {
+            let request = HttpRequest {
+                method: Method::POST,
+                url: url.clone(),
+                headers: self.build_headers(endpoint, payload.clone()).await?,
+                body: Some(payload.clone()),
+            };
+            let response = self.transport.send(request).await?;
+
+            self.handler(response)
+        })
+        .await
+    }
+
+    async fn with_retry<F, Fut>(
+        &self,
+        idempotent: bool,
+        (key, per_minute): (&'static str, u32),
+        request: F,
+    ) -> Result<String, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<String, Error>>,
+    {
+        let policy = self.config.retry_policy();
+        let max_attempts = if policy.applies_to(idempotent) {
+            policy.max_attempts()
+        } else {
+            1
+        };
+        let mut attempt = 1;
+
+        loop {
+            self.limiter.acquire(key, per_minute).await?;
+
+            let result = request().await;
+
+            if let Err(Error::Api(ApiError::RateLimited(_))) = result {
+                self.limiter.penalize(key, per_minute);
+            }
+
+            match result {
+                Err(e) if attempt < max_attempts && policy.should_retry(&e) => {
+                    tokio::time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
     async fn build_headers(
         &self,
         endpoint: &AuthenticatedEndpoint,
         payload: String,
-    ) -> Result<HeaderMap, BoxError> {
-        let nonce: String = auth::generate_nonce().await?;
-        let path = endpoint
-            .to_string()
-            .strip_prefix(AuthenticatedEndpoint::HOST)
-            .ok_or("Invalid endpoint")?
-            .to_owned();
-        let signature_path = format!("/api{}{}{}", path, nonce, payload);
+    ) -> Result<HeaderMap, Error> {
+        let nonce = self.config.nonce_provider().next_nonce()?.to_string();
+        // endpoints are formatted relative to the configured host, so the path is signed as-is
+        let signature_path = format!("/api{}{}{}", endpoint, nonce, payload);
 
-        let signature = auth::sign_payload(self.secret_key.as_bytes(), signature_path.as_bytes())?;
+        let signature = sign_async(&self.signer, signature_path).await?;
 
         let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT, HeaderValue::from_static("bitfinex-rs"));
+        headers.insert(USER_AGENT, self.user_agent.clone());
         headers.insert(
             HeaderName::from_static("bfx-nonce"),
             HeaderValue::from_str(nonce.as_str())?,
         );
         headers.insert(
             HeaderName::from_static("bfx-apikey"),
-            HeaderValue::from_str(self.api_key.as_str())?,
+            HeaderValue::from_str(self.signer.api_key())?,
         );
         headers.insert(
             HeaderName::from_static("bfx-signature"),
@@ -91,32 +181,117 @@ impl Client {
         Ok(headers)
     }
 
-    async fn handler(&self, response: Response) -> Result<String, BoxError> {
-        match response.status() {
-            StatusCode::OK => {
-                let body = response.text().await?;
+    fn handler(&self, response: HttpResponse) -> Result<String, Error> {
+        match response.status {
+            StatusCode::OK => Ok(response.body),
+            s => Err(ApiError::from_response(s, &response.body).into()),
+        }
+    }
+}
 
-                return Ok(body);
-            }
-            StatusCode::INTERNAL_SERVER_ERROR => {
-                bail!("Internal Server Error: {}", response.text().await?);
-            }
-            StatusCode::SERVICE_UNAVAILABLE => {
-                bail!("Service Unavailable: {}", response.text().await?);
-            }
-            StatusCode::UNAUTHORIZED => {
-                bail!("Unauthorized: {}", response.text().await?);
-            }
-            StatusCode::BAD_REQUEST => {
-                bail!(format!("Bad Request: {}", response.text().await?));
-            }
-            s => {
-                bail!(format!(
-                    "Received response {}: {}",
-                    s,
-                    response.text().await?
-                ));
-            }
-        };
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::retry::RetryPolicy;
+    use crate::transport::MockTransport;
+
+    static WALLETS: &str = "/v2/auth/r/wallets";
+    static SUBMIT_ORDER: &str = "/v2/auth/w/order/submit";
+
+    fn client(transport: Arc<MockTransport>, policy: RetryPolicy) -> Client {
+        let config = ClientConfig::new()
+            .with_transport(transport)
+            .with_retry_policy(
+                policy.with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+            );
+
+        Client::with_config(Some("key".into()), Some("secret".into()), config).unwrap()
+    }
+
+    fn unavailable(transport: MockTransport, path: &str) -> MockTransport {
+        transport.with_status_response(path, None, StatusCode::SERVICE_UNAVAILABLE, "")
+    }
+
+    #[tokio::test]
+    async fn reads_are_retried_until_success() {
+        let transport = unavailable(unavailable(MockTransport::new(), WALLETS), WALLETS)
+            .with_response(WALLETS, None, "[]");
+        let transport = Arc::new(transport);
+        let client = client(transport.clone(), RetryPolicy::new());
+
+        let body = client
+            .post_signed(&AuthenticatedEndpoint::Wallets, "{}".into())
+            .await
+            .unwrap();
+
+        assert_eq!(body, "[]");
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reads_give_up_after_max_attempts() {
+        let transport = Arc::new(unavailable(MockTransport::new(), WALLETS));
+        let client = client(transport.clone(), RetryPolicy::new().with_max_attempts(2));
+
+        let error = client
+            .post_signed(&AuthenticatedEndpoint::Wallets, "{}".into())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.api_error().map(ApiError::status), Some(503));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_listed_statuses_are_retried() {
+        let transport = MockTransport::new()
+            .with_status_response(WALLETS, None, StatusCode::BAD_REQUEST, "")
+            .with_response(WALLETS, None, "[]");
+        let transport = Arc::new(transport);
+        let client = client(transport.clone(), RetryPolicy::new());
+
+        let error = client
+            .post_signed(&AuthenticatedEndpoint::Wallets, "{}".into())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.api_error().map(ApiError::status), Some(400));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn writes_are_not_retried() {
+        let transport =
+            unavailable(MockTransport::new(), SUBMIT_ORDER).with_response(SUBMIT_ORDER, None, "[]");
+        let transport = Arc::new(transport);
+        let client = client(transport.clone(), RetryPolicy::new());
+
+        let error = client
+            .post_signed(&AuthenticatedEndpoint::SubmitOrder, "{}".into())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.api_error().map(ApiError::status), Some(503));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn writes_are_retried_when_enabled() {
+        let transport =
+            unavailable(MockTransport::new(), SUBMIT_ORDER).with_response(SUBMIT_ORDER, None, "[]");
+        let transport = Arc::new(transport);
+        let client = client(
+            transport.clone(),
+            RetryPolicy::new().with_retry_writes(true),
+        );
+
+        client
+            .post_signed(&AuthenticatedEndpoint::SubmitOrder, "{}".into())
+            .await
+            .unwrap();
+
+        assert_eq!(transport.requests().len(), 2);
     }
 }
@@ -1,2 +1,2 @@
-pub static USD: &'static str = "USD";
-pub static EUR: &'static str = "EUR";
+pub static USD: &str = "USD";
+pub static EUR: &str = "EUR";
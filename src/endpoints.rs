@@ -1,5 +1,7 @@
+use std::fmt;
+
 use crate::book::BookPrecision;
-use crate::candles::{CandlesSection, CandlesTimeFrame};
+use crate::candles::{CandleKey, CandlesSection};
 
 // TODO: incomplete
 #[derive(Debug)]
@@ -22,70 +24,70 @@ pub enum PublicEndpoint {
         precision: BookPrecision,
     },
     Candles {
-        timeframe: CandlesTimeFrame,
-        symbol: String,
+        key: CandleKey,
         section: CandlesSection,
-        funding_period: Option<String>,
     },
 }
 
 impl PublicEndpoint {
-    const HOST: &'static str = "https://api-pub.bitfinex.com";
+    pub const HOST: &'static str = "https://api-pub.bitfinex.com";
     const PATH: &'static str = "/v2";
+
+    /// Identifies the endpoint when enforcing rate limits.
+    pub(crate) fn rate_limit_key(&self) -> &'static str {
+        match self {
+            PublicEndpoint::Status => "status",
+            PublicEndpoint::Tickers { .. } => "tickers",
+            PublicEndpoint::Ticker { .. } => "ticker",
+            PublicEndpoint::TickersHistory { .. } => "tickers_history",
+            PublicEndpoint::Trades { .. } => "trades",
+            PublicEndpoint::Book { .. } => "book",
+            PublicEndpoint::Candles { .. } => "candles",
+        }
+    }
+
+    /// Maximum number of requests per minute allowed by Bitfinex.
+    pub(crate) fn rate_limit(&self) -> u32 {
+        match self {
+            PublicEndpoint::Status => 30,
+            PublicEndpoint::Tickers { .. } => 30,
+            PublicEndpoint::Ticker { .. } => 90,
+            PublicEndpoint::TickersHistory { .. } => 15,
+            PublicEndpoint::Trades { .. } => 15,
+            PublicEndpoint::Book { .. } => 90,
+            PublicEndpoint::Candles { .. } => 30,
+        }
+    }
 }
 
-impl ToString for PublicEndpoint {
-    fn to_string(&self) -> String {
-        let mut endpoint = format!("{}{}", PublicEndpoint::HOST, PublicEndpoint::PATH);
+/// Formats the endpoint as a path relative to the configured public host.
+impl fmt::Display for PublicEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut endpoint = String::from(PublicEndpoint::PATH);
 
         match self {
             PublicEndpoint::Status => endpoint.push_str("/platform/status"),
             PublicEndpoint::Tickers { symbols } => {
                 let joined_syms = symbols.join(",");
-                endpoint.push_str(&*format!("/tickers?symbols={}", joined_syms));
+                endpoint.push_str(&format!("/tickers?symbols={}", joined_syms));
             }
             PublicEndpoint::Ticker { symbol } => endpoint.push_str(&format!("/ticker/{}", symbol)),
             PublicEndpoint::TickersHistory { symbols } => {
                 let joined_syms = symbols.join(",");
-                endpoint.push_str(&*format!("/tickers/hist?symbols={}", joined_syms));
+                endpoint.push_str(&format!("/tickers/hist?symbols={}", joined_syms));
             }
             PublicEndpoint::Trades { symbol } => {
                 endpoint.push_str(&format!("/ticker/{}/hist", symbol))
             }
             PublicEndpoint::Book { symbol, precision } => {
-                endpoint.push_str(&format!("/book/{}/{}", symbol, precision.to_string()))
+                endpoint.push_str(&format!("/book/{}/{}", symbol, precision))
             }
-            PublicEndpoint::Candles {
-                symbol,
-                funding_period,
-                section,
-                timeframe,
-            } => {
-                let query = match funding_period {
-                    Some(period) => {
-                        format!(
-                            "/candles/trade:{}:{}:{}/{}",
-                            timeframe.to_string(),
-                            symbol.to_string(),
-                            period.to_string(),
-                            section.to_string()
-                        )
-                    }
-                    None => {
-                        format!(
-                            "/candles/trade:{}:{}/{}",
-                            timeframe.to_string(),
-                            symbol.to_string(),
-                            section.to_string()
-                        )
-                    }
-                };
-
-                endpoint.push_str(&query);
+            PublicEndpoint::Candles { key, section } => {
+                endpoint.push_str(&format!("/candles/{}/{}", key, section))
             }
         }
 
-        endpoint
+        write!(f, "{}", endpoint)
     }
 }
 
@@ -120,11 +122,69 @@ impl AuthenticatedEndpoint {
     const READ_PATH: &'static str = "/v2/auth/r";
     const WRITE_PATH: &'static str = "/v2/auth/w";
     const CALC_PATH: &'static str = "/v2/auth/calc";
+
+    /// Identifies the endpoint when enforcing rate limits.
+    pub(crate) fn rate_limit_key(&self) -> &'static str {
+        match self {
+            AuthenticatedEndpoint::Wallets => "auth:wallets",
+            AuthenticatedEndpoint::RetrieveOrders => "auth:orders",
+            AuthenticatedEndpoint::SubmitOrder => "auth:order_submit",
+            AuthenticatedEndpoint::UpdateOrder => "auth:order_update",
+            AuthenticatedEndpoint::CancelOrder => "auth:order_cancel",
+            AuthenticatedEndpoint::OrdersHistory { .. } => "auth:orders_history",
+            AuthenticatedEndpoint::OrderTrades { .. } => "auth:order_trades",
+            AuthenticatedEndpoint::Trades { .. } => "auth:trades",
+            AuthenticatedEndpoint::Ledgers { .. } => "auth:ledgers",
+            AuthenticatedEndpoint::MarginInfo { .. } => "auth:margin_info",
+            AuthenticatedEndpoint::RetrievePositions => "auth:positions",
+            AuthenticatedEndpoint::ClaimPosition => "auth:position_claim",
+            AuthenticatedEndpoint::PositionsHistory => "auth:positions_history",
+            AuthenticatedEndpoint::PositionsSnapshot => "auth:positions_snapshot",
+            AuthenticatedEndpoint::PositionsAudit => "auth:positions_audit",
+            AuthenticatedEndpoint::DerivativePositionCollateral => "auth:deriv_collateral",
+            AuthenticatedEndpoint::DerivativePositionCollateralLimits => {
+                "auth:deriv_collateral_limits"
+            }
+            AuthenticatedEndpoint::UserInfo => "auth:user_info",
+            AuthenticatedEndpoint::Summary => "auth:summary",
+            AuthenticatedEndpoint::FundingInfo { .. } => "auth:funding_info",
+            AuthenticatedEndpoint::WalletTransfer => "auth:transfer",
+        }
+    }
+
+    /// Maximum number of requests per minute allowed by Bitfinex.
+    pub(crate) fn rate_limit(&self) -> u32 {
+        90
+    }
+
+    /// Whether the endpoint only reads data and can therefore be safely retried. Endpoints
+    /// which are not listed here are never retried.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            AuthenticatedEndpoint::Wallets
+                | AuthenticatedEndpoint::RetrieveOrders
+                | AuthenticatedEndpoint::OrdersHistory { .. }
+                | AuthenticatedEndpoint::OrderTrades { .. }
+                | AuthenticatedEndpoint::Trades { .. }
+                | AuthenticatedEndpoint::Ledgers { .. }
+                | AuthenticatedEndpoint::MarginInfo { .. }
+                | AuthenticatedEndpoint::RetrievePositions
+                | AuthenticatedEndpoint::PositionsHistory
+                | AuthenticatedEndpoint::PositionsSnapshot
+                | AuthenticatedEndpoint::PositionsAudit
+                | AuthenticatedEndpoint::DerivativePositionCollateralLimits
+                | AuthenticatedEndpoint::UserInfo
+                | AuthenticatedEndpoint::Summary
+                | AuthenticatedEndpoint::FundingInfo { .. }
+        )
+    }
 }
 
-impl ToString for AuthenticatedEndpoint {
-    fn to_string(&self) -> String {
-        let mut endpoint = String::from(AuthenticatedEndpoint::HOST);
+/// Formats the endpoint as a path relative to the configured authenticated host.
+impl fmt::Display for AuthenticatedEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut endpoint = String::new();
 
         match self {
             AuthenticatedEndpoint::Wallets => {
@@ -179,7 +239,7 @@ impl ToString for AuthenticatedEndpoint {
             AuthenticatedEndpoint::MarginInfo { key } => endpoint.push_str(&format!(
                 "{}/info/margin/{}",
                 AuthenticatedEndpoint::READ_PATH,
-                key.to_string()
+                key
             )),
             AuthenticatedEndpoint::RetrievePositions => {
                 endpoint.push_str(&format!("{}/positions", AuthenticatedEndpoint::READ_PATH))
@@ -226,7 +286,7 @@ impl ToString for AuthenticatedEndpoint {
             }
         }
 
-        endpoint
+        write!(f, "{}", endpoint)
     }
 }
 
@@ -237,12 +297,36 @@ pub enum MarginInfoKey {
     SymAll,
 }
 
-impl ToString for MarginInfoKey {
-    fn to_string(&self) -> String {
+impl fmt::Display for MarginInfoKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            MarginInfoKey::Base => "base".into(),
-            MarginInfoKey::Symbol(s) => s.to_owned(),
-            MarginInfoKey::SymAll => "sym_all".into(),
+            MarginInfoKey::Base => write!(f, "base"),
+            MarginInfoKey::Symbol(s) => write!(f, "{}", s),
+            MarginInfoKey::SymAll => write!(f, "sym_all"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candles::CandlesTimeFrame;
+
+    #[test]
+    fn candles_paths_contain_the_key() {
+        let endpoint = PublicEndpoint::Candles {
+            key: CandleKey::funding_aggregated("USD", CandlesTimeFrame::OneMinute, 30, 2, 30),
+            section: CandlesSection::Hist,
+        };
+        assert_eq!(
+            endpoint.to_string(),
+            "/v2/candles/trade:1m:fUSD:a30:p2:p30/hist"
+        );
+
+        let endpoint = PublicEndpoint::Candles {
+            key: CandleKey::trading("BTCUSD", CandlesTimeFrame::OneWeek),
+            section: CandlesSection::Last,
+        };
+        assert_eq!(endpoint.to_string(), "/v2/candles/trade:7D:tBTCUSD/last");
+    }
+}
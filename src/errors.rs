@@ -1,33 +1,399 @@
-use std;
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
 
-use reqwest;
-use serde_json;
-use tungstenite;
-use url;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
-pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
 
-error_chain! {
-    types {
-        Error, ErrorKind, ResultExt, Result;
+/// The error type returned by every fallible operation of the library.
+#[derive(Debug)]
+pub enum Error {
+    /// The HTTP request could not be sent or its response could not be read
+    Http(reqwest::Error),
+    /// A payload could not be (de)serialized. `payload` holds the raw data that failed to decode.
+    Json {
+        source: serde_json::Error,
+        payload: Option<String>,
+    },
+    /// The request could not be signed (invalid key material, nonce generation failure, ...)
+    Signing(Box<dyn StdError + Send + Sync>),
+    /// The websocket connection failed
+    WebSocket(Box<tungstenite::Error>),
+    /// The websocket connection was closed
+    Disconnected(String),
+    /// An URL could not be parsed
+    Url(url::ParseError),
+    /// A local I/O operation failed
+    Io(std::io::Error),
+    /// The Bitfinex API answered with an error
+    Api(ApiError),
+    /// The request would exceed the client-side rate limit of the endpoint
+    RateLimited {
+        endpoint: &'static str,
+        retry_after: Duration,
+    },
+    /// The arguments of a request are not valid
+    InvalidInput(String),
+    /// The websocket server rejected a request or broke the protocol (skipped sequence number, ...)
+    Protocol(String),
+}
+
+impl Error {
+    /// Returns the API error, if the failure was reported by Bitfinex.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Json {
+                source,
+                payload: Some(payload),
+            } => write!(f, "JSON error: {} (payload: {})", source, payload),
+            Error::Json {
+                source,
+                payload: None,
+            } => write!(f, "JSON error: {}", source),
+            Error::Signing(e) => write!(f, "Signing error: {}", e),
+            Error::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Error::Disconnected(reason) => write!(f, "Disconnected: {}", reason),
+            Error::Url(e) => write!(f, "Invalid URL: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Api(e) => write!(f, "{}", e),
+            Error::RateLimited {
+                endpoint,
+                retry_after,
+            } => write!(
+                f,
+                "Rate limit of {} exceeded, retry after {:?}",
+                endpoint, retry_after
+            ),
+            Error::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            Error::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Json { source, .. } => Some(source),
+            Error::Signing(e) => Some(e.as_ref()),
+            Error::WebSocket(e) => Some(e.as_ref()),
+            Error::Url(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Api(e) => Some(e),
+            Error::Disconnected(_)
+            | Error::RateLimited { .. }
+            | Error::InvalidInput(_)
+            | Error::Protocol(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json {
+            source: e,
+            payload: None,
+        }
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for Error {
+    fn from(e: reqwest::header::InvalidHeaderValue) -> Self {
+        Error::Signing(Box::new(e))
+    }
+}
+
+impl From<std::time::SystemTimeError> for Error {
+    fn from(e: std::time::SystemTimeError) -> Self {
+        Error::Signing(Box::new(e))
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Url(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(e: ApiError) -> Self {
+        Error::Api(e)
+    }
+}
+
+/// Deserializes `payload`, keeping the raw data in the error if decoding fails.
+pub(crate) fn from_json<T: DeserializeOwned>(payload: &str) -> Result<T> {
+    serde_json::from_str(payload).map_err(|source| Error::Json {
+        source,
+        payload: Some(payload.to_owned()),
+    })
+}
+
+/// Like [`from_json`], for a value extracted from `payload`.
+pub(crate) fn from_value<T: DeserializeOwned>(
+    value: serde_json::Value,
+    payload: &str,
+) -> Result<T> {
+    serde_json::from_value(value).map_err(|source| Error::Json {
+        source,
+        payload: Some(payload.to_owned()),
+    })
+}
+
+/// The error body returned by Bitfinex, together with the HTTP status of the response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Bitfinex error code (e.g. 10114), if the body contained one
+    pub code: Option<u64>,
+    /// Error message, or the raw body if it could not be parsed
+    pub message: String,
+}
+
+/// An error reported by the Bitfinex API.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiError {
+    /// The nonce is lower than or equal to the last one used with this API key ("nonce: small")
+    NonceTooSmall(ErrorResponse),
+    /// Too many requests, the IP may be banned for 60 seconds
+    RateLimited(ErrorResponse),
+    /// The API key is invalid, lacks permissions or the signature does not match
+    InvalidApiKey(ErrorResponse),
+    /// Not enough balance to complete the operation
+    InsufficientFunds(ErrorResponse),
+    /// The platform is under maintenance
+    Maintenance(ErrorResponse),
+    /// Any other error
+    Other(ErrorResponse),
+}
+
+impl ApiError {
+    const ERR_AUTH_FAIL: u64 = 10100;
+    const ERR_AUTH_NONCE: u64 = 10114;
+    const ERR_RATE_LIMIT: u64 = 11010;
+    const MAINTENANCE: u64 = 20060;
+
+    /// Builds the error from a non-successful response body.
+    ///
+    /// Bitfinex usually answers with `["error", code, "message"]`, but some errors (e.g. rate
+    /// limits) come back as `{"error": "ERR_RATE_LIMIT"}` or as plain text.
+    pub(crate) fn from_response(status: StatusCode, body: &str) -> Self {
+        let (code, message) = match serde_json::from_str::<Value>(body) {
+            Ok(Value::Array(values)) => (
+                values.get(1).and_then(Value::as_u64),
+                values
+                    .get(2)
+                    .and_then(Value::as_str)
+                    .unwrap_or(body)
+                    .to_owned(),
+            ),
+            Ok(Value::Object(map)) => (
+                map.get("code").and_then(Value::as_u64),
+                map.get("message")
+                    .or_else(|| map.get("error"))
+                    .and_then(Value::as_str)
+                    .unwrap_or(body)
+                    .to_owned(),
+            ),
+            _ => (None, body.to_owned()),
+        };
+
+        let response = ErrorResponse {
+            status: status.as_u16(),
+            code,
+            message,
+        };
+        let message = response.message.to_lowercase();
+
+        if code == Some(Self::ERR_AUTH_NONCE) || message.contains("nonce: small") {
+            ApiError::NonceTooSmall(response)
+        } else if status == StatusCode::TOO_MANY_REQUESTS
+            || code == Some(Self::ERR_RATE_LIMIT)
+            || message.contains("ratelimit")
+            || message.contains("rate_limit")
+        {
+            ApiError::RateLimited(response)
+        } else if code == Some(Self::ERR_AUTH_FAIL) || message.contains("apikey") {
+            ApiError::InvalidApiKey(response)
+        } else if message.contains("not enough") || message.contains("insufficient") {
+            ApiError::InsufficientFunds(response)
+        } else if code == Some(Self::MAINTENANCE) || message.contains("maintenance") {
+            ApiError::Maintenance(response)
+        } else {
+            ApiError::Other(response)
+        }
+    }
+
+    pub fn response(&self) -> &ErrorResponse {
+        match self {
+            ApiError::NonceTooSmall(r)
+            | ApiError::RateLimited(r)
+            | ApiError::InvalidApiKey(r)
+            | ApiError::InsufficientFunds(r)
+            | ApiError::Maintenance(r)
+            | ApiError::Other(r) => r,
+        }
     }
 
-    errors {
-        Internal(t: String) {
-            description("invalid toolchain name")
-            display("invalid toolchain name: '{}'", t)
+    pub fn status(&self) -> u16 {
+        self.response().status
+    }
+
+    pub fn code(&self) -> Option<u64> {
+        self.response().code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.response().message
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ApiError::NonceTooSmall(_) => "Nonce too small",
+            ApiError::RateLimited(_) => "Rate limited",
+            ApiError::InvalidApiKey(_) => "Invalid API key",
+            ApiError::InsufficientFunds(_) => "Insufficient funds",
+            ApiError::Maintenance(_) => "Maintenance",
+            ApiError::Other(_) => "API error",
+        };
+
+        match self.code() {
+            Some(code) => write!(
+                f,
+                "{} (HTTP {}, code {}): {}",
+                kind,
+                self.status(),
+                code,
+                self.message()
+            ),
+            None => write!(f, "{} (HTTP {}): {}", kind, self.status(), self.message()),
         }
     }
+}
+
+impl StdError for ApiError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(status: u16, body: &str) -> ApiError {
+        ApiError::from_response(StatusCode::from_u16(status).unwrap(), body)
+    }
 
-    foreign_links {
-        ReqError(reqwest::Error);
-        InvalidHeaderError(reqwest::header::InvalidHeaderValue);
-        IoError(std::io::Error);
-        ParseFloatError(std::num::ParseFloatError);
-        UrlParserError(url::ParseError);
-        Json(serde_json::Error);
-        Tungstenite(tungstenite::Error);
-        TimestampError(std::time::SystemTimeError);
+    fn variant(error: &ApiError) -> &'static str {
+        match error {
+            ApiError::NonceTooSmall(_) => "NonceTooSmall",
+            ApiError::RateLimited(_) => "RateLimited",
+            ApiError::InvalidApiKey(_) => "InvalidApiKey",
+            ApiError::InsufficientFunds(_) => "InsufficientFunds",
+            ApiError::Maintenance(_) => "Maintenance",
+            ApiError::Other(_) => "Other",
+        }
     }
 
+    #[test]
+    fn api_errors_are_classified() {
+        let cases: &[(u16, &str, &str, Option<u64>, &str)] = &[
+            (
+                500,
+                r#"["error",10114,"nonce: small"]"#,
+                "NonceTooSmall",
+                Some(10114),
+                "nonce: small",
+            ),
+            (
+                429,
+                r#"{"error":"ERR_RATE_LIMIT"}"#,
+                "RateLimited",
+                None,
+                "ERR_RATE_LIMIT",
+            ),
+            (
+                500,
+                r#"["error",11010,"ratelimit: error"]"#,
+                "RateLimited",
+                Some(11010),
+                "ratelimit: error",
+            ),
+            (
+                500,
+                r#"["error",10100,"apikey: invalid"]"#,
+                "InvalidApiKey",
+                Some(10100),
+                "apikey: invalid",
+            ),
+            (
+                500,
+                r#"["error",10001,"Invalid order: not enough exchange balance for -0.1 tBTCUSD at 9000"]"#,
+                "InsufficientFunds",
+                Some(10001),
+                "Invalid order: not enough exchange balance for -0.1 tBTCUSD at 9000",
+            ),
+            (
+                503,
+                r#"["error",20060,"maintenance"]"#,
+                "Maintenance",
+                Some(20060),
+                "maintenance",
+            ),
+            (
+                500,
+                r#"["error",10020,"symbol: invalid"]"#,
+                "Other",
+                Some(10020),
+                "symbol: invalid",
+            ),
+            (
+                502,
+                "<html><body>502 Bad Gateway</body></html>",
+                "Other",
+                None,
+                "<html><body>502 Bad Gateway</body></html>",
+            ),
+        ];
+
+        for &(status, body, kind, code, message) in cases {
+            let error = classify(status, body);
+
+            assert_eq!(variant(&error), kind, "{}", body);
+            assert_eq!(error.status(), status);
+            assert_eq!(error.code(), code, "{}", body);
+            assert_eq!(error.message(), message);
+        }
+    }
 }
@@ -1,24 +1,97 @@
+use crate::account::{Balance, FundingInfo, MarginBase, MarginSymbol, Wallet};
 use crate::book::{
     FundingCurrency as BookFundingCurrency, RawBook, TradingPair as BookTradingPair,
 };
 use crate::candles::Candle;
+use crate::errors::Error;
+use crate::orders::ActiveOrder;
+use crate::positions::Position;
+use crate::responses::{Notification, TradeResponse};
+use crate::status::{DerivativeStatus, Liquidation};
 use crate::ticker::{FundingCurrency, TradingPairTicker};
 use crate::trades::{FundingCurrency as TradesFundingCurrency, TradingPair as TradesTradingPair};
+use crate::websockets::{Channel, Subscription};
+
+/// A message received on the websocket, as yielded by
+/// [`crate::async_websockets::EventStream`].
+#[derive(Debug)]
+pub enum WebSocketEvent {
+    Info(NotificationEvent),
+    /// The answer to a `conf` event
+    Configured(NotificationEvent),
+    Auth(NotificationEvent),
+    Subscribed(NotificationEvent),
+    /// A channel was unsubscribed, no more data is received on it
+    Unsubscribed(NotificationEvent),
+    /// A subscribe or unsubscribe request was rejected
+    SubscriptionError(NotificationEvent),
+    /// Channel data, along with the channel it was received on
+    Data(Channel, DataEvent, FrameInfo),
+    /// The checksum of a book did not match its local copy. The book must be cleared, its channel
+    /// is subscribed again
+    ChecksumMismatch(Channel),
+    /// An update of the authenticated account, received on channel 0
+    Account(DataEvent, FrameInfo),
+    /// The answer to a ping, see [`crate::config::ClientConfig::with_ping_interval`]
+    Pong(NotificationEvent),
+    /// A sequence number was skipped, some frames were lost. Only checked once enabled with
+    /// [`crate::websockets::ConfFlags::SEQ_ALL`]
+    SequenceGap(SequenceGap),
+    /// A frame which is not modelled, e.g. data of a channel whose subscription was not seen
+    Unknown(String),
+    /// The connection dropped and is being re-established, see
+    /// [`crate::config::ClientConfig::with_reconnect_policy`]
+    Disconnected(Error),
+    /// The connection was re-established, authenticated and subscribed again
+    Reconnected,
+    /// The book of this subscription is stale and must be cleared, a new snapshot follows the
+    /// reconnection
+    BookReset(Subscription),
+}
+
+/// The server timestamp and sequence numbers trailing a frame, once enabled with
+/// [`crate::websockets::ConfFlags`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameInfo {
+    /// Milliseconds since the epoch, with [`crate::websockets::ConfFlags::TIMESTAMP`]
+    pub timestamp: Option<u64>,
+    /// The sequence number of the public frames, with [`crate::websockets::ConfFlags::SEQ_ALL`]
+    pub seq: Option<u64>,
+    /// The sequence number of the account frames, with
+    /// [`crate::websockets::ConfFlags::SEQ_ALL`]
+    pub auth_seq: Option<u64>,
+}
+
+/// A skipped sequence number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SequenceGap {
+    pub expected: u64,
+    pub received: u64,
+    /// Whether the account sequence was skipped, rather than the public one
+    pub account: bool,
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 #[serde(rename_all = "camelCase")]
 pub enum NotificationEvent {
     Auth(AuthMessage),
-    Info(InfoMessage),
     TradingSubscribed(TradingSubscriptionMessage),
     FundingSubscribed(FundingSubscriptionMessage),
     CandlesSubscribed(CandlesSubscriptionMessage),
     RawBookSubscribed(RawBookSubscriptionMessage),
+    StatusSubscribed(StatusSubscriptionMessage),
+    Unsubscribed(UnsubscribedMessage),
+    SubscriptionError(SubscriptionErrorMessage),
+    Pong(PongMessage),
+    Conf(ConfMessage),
+    // last, every field but `event` is optional
+    Info(InfoMessage),
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+/// The data of a channel or of the authenticated account. Built from the kind of the channel
+/// or the account event code rather than guessed from the shape of the payload.
+#[derive(Debug)]
 pub enum DataEvent {
     TickerTradingEvent(i32, TradingPairTicker),
     TickerFundingEvent(i32, FundingCurrency),
@@ -32,13 +105,60 @@ pub enum DataEvent {
     BookFundingUpdateEvent(i32, BookFundingCurrency),
     RawBookEvent(i32, RawBook),
     RawBookUpdateEvent(i32, Vec<RawBook>),
+    /// Several updates of a book at once, once enabled with
+    /// [`crate::websockets::ConfFlags::BULK_UPDATES`]
+    BookTradingBulkUpdateEvent(i32, Vec<BookTradingPair>),
+    BookFundingBulkUpdateEvent(i32, Vec<BookFundingCurrency>),
+    RawBookBulkUpdateEvent(i32, Vec<RawBook>),
     CandlesSnapshotEvent(i32, Vec<Candle>),
     CandlesUpdateEvent(i32, Candle),
-    HeartbeatEvent(i32, String),
+    /// The status of a derivatives pair, first received once subscribed
+    DerivativeStatusSnapshotEvent(i32, DerivativeStatus),
+    /// The status of a derivatives pair, received whole on every change
+    DerivativeStatusUpdateEvent(i32, DerivativeStatus),
+    LiquidationSnapshotEvent(i32, Vec<Liquidation>),
+    LiquidationUpdateEvent(i32, Vec<Liquidation>),
+    /// The CRC32 of the top 25 levels of a book, once enabled with
+    /// [`crate::websockets::ConfFlags::OB_CHECKSUM`]
+    BookChecksumEvent(i32, i32),
+    /// `os`: the active orders, sent once authenticated
+    OrderSnapshotEvent(Vec<ActiveOrder>),
+    /// `on`
+    OrderNewEvent(ActiveOrder),
+    /// `ou`
+    OrderUpdateEvent(ActiveOrder),
+    /// `oc`: the order was cancelled or fully executed
+    OrderCancelEvent(ActiveOrder),
+    /// `ps`: the active positions, sent once authenticated
+    PositionSnapshotEvent(Vec<Position>),
+    /// `pn`
+    PositionNewEvent(Position),
+    /// `pu`
+    PositionUpdateEvent(Position),
+    /// `pc`
+    PositionCloseEvent(Position),
+    /// `ws`: the wallets, sent once authenticated
+    WalletSnapshotEvent(Vec<Wallet>),
+    /// `wu`
+    WalletUpdateEvent(Wallet),
+    /// `te`: a trade was executed, the fee is not known yet
+    TradeExecutedEvent(TradeResponse),
+    /// `tu`: the executed trade, along with its fee
+    TradeExecutionUpdateEvent(TradeResponse),
+    /// `bu`
+    BalanceUpdateEvent(Balance),
+    /// `miu` for the base margin information
+    MarginBaseUpdateEvent(MarginBase),
+    /// `miu` for the margin information of a trading pair
+    MarginSymbolUpdateEvent(MarginSymbol),
+    /// `fiu`
+    FundingInfoUpdateEvent(FundingInfo),
+    /// `n`
+    AccountNotificationEvent(Notification),
 }
 
-#[serde(rename_all = "camelCase")]
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AuthMessage {
     pub event: String,
     pub status: String,
@@ -55,22 +175,72 @@ impl AuthMessage {
     }
 }
 
-#[serde(rename_all = "camelCase")]
 #[derive(Debug, Deserialize)]
+pub struct PongMessage {
+    pub event: String,
+    pub ts: u64,
+    pub cid: u64,
+}
+
+/// The greeting of the server, sent once connected, or a notice carrying a code.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct InfoMessage {
     pub event: String,
-    pub version: u16,
-    pub server_id: String,
-    pub platform: Platform,
+    pub version: Option<u16>,
+    pub server_id: Option<String>,
+    pub platform: Option<Platform>,
+    pub code: Option<u32>,
+    pub msg: Option<String>,
+}
+
+impl InfoMessage {
+    pub fn info_code(&self) -> Option<InfoCode> {
+        self.code.map(InfoCode::from)
+    }
+
+    /// Whether the platform is in maintenance, as announced by the greeting or a notice.
+    pub fn is_maintenance(&self) -> Option<bool> {
+        match self.info_code() {
+            Some(InfoCode::MaintenanceStarted) => Some(true),
+            Some(InfoCode::MaintenanceEnded) => Some(false),
+            _ => self.platform.as_ref().map(|platform| platform.status == 0),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Platform {
+    /// 1 when operative, 0 during maintenance
     pub status: u16,
 }
 
-#[serde(rename_all = "camelCase")]
+/// The code of an [`InfoMessage`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfoCode {
+    /// The server is stopping and asks to reconnect (code 20051). The websocket clients
+    /// reconnect on their own, see [`crate::config::ClientConfig::with_reconnect_policy`].
+    ReconnectRequested,
+    /// The platform entered maintenance, orders are held until it ends (code 20060)
+    MaintenanceStarted,
+    /// The maintenance is over, every channel is subscribed again (code 20061)
+    MaintenanceEnded,
+    Other(u32),
+}
+
+impl From<u32> for InfoCode {
+    fn from(code: u32) -> Self {
+        match code {
+            20051 => InfoCode::ReconnectRequested,
+            20060 => InfoCode::MaintenanceStarted,
+            20061 => InfoCode::MaintenanceEnded,
+            code => InfoCode::Other(code),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TradingSubscriptionMessage {
     pub event: String,
     pub channel: String,
@@ -79,8 +249,8 @@ pub struct TradingSubscriptionMessage {
     pub pair: String,
 }
 
-#[serde(rename_all = "camelCase")]
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FundingSubscriptionMessage {
     pub event: String,
     pub channel: String,
@@ -89,8 +259,8 @@ pub struct FundingSubscriptionMessage {
     pub currency: String,
 }
 
-#[serde(rename_all = "camelCase")]
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CandlesSubscriptionMessage {
     pub event: String,
     pub channel: String,
@@ -98,8 +268,71 @@ pub struct CandlesSubscriptionMessage {
     pub key: String,
 }
 
+/// Shaped like [`CandlesSubscriptionMessage`], only decoded when `channel` is `status`.
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct StatusSubscriptionMessage {
+    pub event: String,
+    pub channel: String,
+    pub chan_id: u32,
+    pub key: String,
+}
+
 #[derive(Debug, Deserialize)]
+pub struct ConfMessage {
+    pub event: String,
+    pub status: String,
+    pub flags: Option<u32>,
+}
+
+impl ConfMessage {
+    pub fn is_ok(&self) -> bool {
+        self.status == "OK"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsubscribedMessage {
+    pub event: String,
+    pub status: String,
+    pub chan_id: u32,
+}
+
+impl UnsubscribedMessage {
+    pub fn is_ok(&self) -> bool {
+        self.status == "OK"
+    }
+}
+
+/// An `error` event. The fields of the rejected request are echoed back, when it was a
+/// subscription.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionErrorMessage {
+    pub event: String,
+    pub code: u32,
+    pub msg: String,
+    pub channel: Option<String>,
+    pub chan_id: Option<u32>,
+    pub symbol: Option<String>,
+    pub key: Option<String>,
+}
+
+impl SubscriptionErrorMessage {
+    /// The channel is already subscribed (code 10301).
+    pub fn is_already_subscribed(&self) -> bool {
+        self.code == 10301
+    }
+
+    /// The channel does not exist (code 10302).
+    pub fn is_unknown_channel(&self) -> bool {
+        self.code == 10302
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RawBookSubscriptionMessage {
     pub event: String,
     pub channel: String,
@@ -1,8 +1,6 @@
-use serde_json::from_str;
-
 use crate::client::Client;
 use crate::endpoints::AuthenticatedEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Entry {
@@ -40,7 +38,7 @@ impl Ledger {
         start: u128,
         end: u128,
         limit: i32,
-    ) -> Result<Vec<Entry>, BoxError>
+    ) -> Result<Vec<Entry>, Error>
     where
         S: Into<String>,
     {
@@ -57,6 +55,6 @@ impl Ledger {
             .post_signed_params(&endpoint, "{}".into(), &params)
             .await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 }
@@ -8,8 +8,6 @@
 
 #[macro_use]
 extern crate bitflags;
-#[macro_use]
-extern crate error_chain;
 extern crate hex;
 extern crate reqwest;
 extern crate ring;
@@ -21,22 +19,33 @@ extern crate serde_json;
 extern crate tungstenite;
 extern crate url;
 
-mod auth;
 mod client;
+mod connector;
 mod endpoints;
 mod ledger;
+mod protocol;
 mod trades;
 
 pub mod account;
 pub mod api;
+pub mod async_websockets;
 pub mod book;
 pub mod candles;
+pub mod cassette;
+pub mod config;
 pub mod currency;
 pub mod errors;
 pub mod events;
+pub mod nonce;
 pub mod orders;
 pub mod pairs;
+pub mod pool;
 pub mod positions;
+pub mod ratelimit;
 pub mod responses;
+pub mod retry;
+pub mod signer;
+pub mod status;
 pub mod ticker;
+pub mod transport;
 pub mod websockets;
@@ -0,0 +1,219 @@
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fs2::FileExt;
+
+use crate::errors::Error;
+
+/// Generates the nonces used to sign authenticated requests.
+///
+/// Bitfinex rejects any nonce that is not greater than the last one used with the same API key,
+/// so implementations must return strictly increasing values.
+pub trait NonceProvider: Debug + Send + Sync {
+    fn next_nonce(&self) -> Result<u64, Error>;
+}
+
+fn now_micros() -> Result<u64, Error> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    Ok(since_epoch.as_micros() as u64)
+}
+
+/// In-memory nonce provider based on the microsecond clock.
+///
+/// Returns the current timestamp, or the previous nonce plus one if the clock did not advance
+/// (or went backwards) since the last call.
+#[derive(Debug, Default)]
+pub struct AtomicNonce {
+    last: AtomicU64,
+}
+
+impl AtomicNonce {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The provider shared by every client that does not configure its own.
+    pub fn global() -> Arc<AtomicNonce> {
+        static GLOBAL: OnceLock<Arc<AtomicNonce>> = OnceLock::new();
+
+        GLOBAL.get_or_init(|| Arc::new(AtomicNonce::new())).clone()
+    }
+}
+
+impl NonceProvider for AtomicNonce {
+    fn next_nonce(&self) -> Result<u64, Error> {
+        let now = now_micros()?;
+        let previous = self
+            .last
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(now.max(last + 1))
+            })
+            // the closure always returns Some
+            .unwrap_or_else(|last| last);
+
+        Ok(now.max(previous + 1))
+    }
+}
+
+/// Nonce provider persisting the last nonce to a file.
+///
+/// The file is locked while a nonce is generated, so several processes sharing the same API key
+/// and the same file never produce a nonce lower than one already used.
+///
+/// Every nonce takes an exclusive lock and flushes the file to disk, blocking the calling thread,
+/// including the async runtime worker sending the request. Prefer [`AtomicNonce`] unless the API
+/// key is shared between processes.
+#[derive(Debug)]
+pub struct FileNonce {
+    path: PathBuf,
+}
+
+impl FileNonce {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileNonce {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    fn next_locked(file: &mut File) -> Result<u64, Error> {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        // a new file is empty, anything else must be a nonce or the floor would be lost
+        let last: u64 = match content.trim() {
+            "" => 0,
+            last => last.parse().map_err(|_| {
+                Error::Signing(format!("Invalid nonce file content: {:?}", last).into())
+            })?,
+        };
+        let nonce = now_micros()?.max(last + 1);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(nonce.to_string().as_bytes())?;
+        file.sync_data()?;
+
+        Ok(nonce)
+    }
+}
+
+impl NonceProvider for FileNonce {
+    fn next_nonce(&self) -> Result<u64, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+
+        file.lock_exclusive()?;
+        let nonce = FileNonce::next_locked(&mut file);
+        FileExt::unlock(&file)?;
+
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::thread;
+
+    use super::*;
+
+    fn nonce_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("bitfinex-nonce-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Draws `count` nonces from each provider on its own thread, checking that each thread sees
+    /// them increase and that no nonce is handed out twice.
+    fn assert_monotonic(providers: Vec<Arc<dyn NonceProvider>>, count: usize) {
+        let handles: Vec<_> = providers
+            .into_iter()
+            .map(|provider| {
+                thread::spawn(move || {
+                    let nonces: Vec<u64> =
+                        (0..count).map(|_| provider.next_nonce().unwrap()).collect();
+
+                    assert!(nonces.windows(2).all(|pair| pair[0] < pair[1]));
+                    nonces
+                })
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+
+        for handle in handles {
+            for nonce in handle.join().unwrap() {
+                assert!(seen.insert(nonce), "nonce {} handed out twice", nonce);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_nonce_is_monotonic_across_threads() {
+        let provider: Arc<dyn NonceProvider> = Arc::new(AtomicNonce::new());
+
+        assert_monotonic(vec![provider; 8], 1000);
+    }
+
+    #[test]
+    fn file_nonce_is_monotonic_across_instances() {
+        let path = nonce_file("threads");
+        let providers = (0..4)
+            .map(|_| Arc::new(FileNonce::new(&path)) as Arc<dyn NonceProvider>)
+            .collect();
+
+        assert_monotonic(providers, 25);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_nonce_persists_across_instances() {
+        let path = nonce_file("persist");
+        // far in the future, so the clock cannot catch up during the test
+        let floor = now_micros().unwrap() + 3_600_000_000;
+        std::fs::write(&path, floor.to_string()).unwrap();
+
+        assert_eq!(FileNonce::new(&path).next_nonce().unwrap(), floor + 1);
+        assert_eq!(FileNonce::new(&path).next_nonce().unwrap(), floor + 2);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            (floor + 2).to_string()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_nonce_starts_from_an_empty_file() {
+        let path = nonce_file("empty");
+        std::fs::write(&path, "").unwrap();
+
+        assert!(FileNonce::new(&path).next_nonce().unwrap() > 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_nonce_rejects_a_corrupt_file() {
+        let path = nonce_file("corrupt");
+        std::fs::write(&path, "16000000\0\0").unwrap();
+
+        assert!(matches!(
+            FileNonce::new(&path).next_nonce(),
+            Err(Error::Signing(_))
+        ));
+        // the file is left untouched for inspection
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "16000000\0\0");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,13 +1,13 @@
 use std::fmt::Display;
 
 use chrono::{DateTime, NaiveDate, TimeZone};
-use serde::de::Error;
+use serde::de::Error as DeError;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde_json::{from_str, Value};
+use serde_json::Value;
 
 use crate::client::Client;
 use crate::endpoints::AuthenticatedEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 use crate::responses::OrderResponse;
 
 #[derive(Serialize, Clone, Debug)]
@@ -487,38 +487,38 @@ impl OrderForm {
         self
     }
 
-    pub fn with_price_trailing(mut self, trailing: Option<f64>) -> Result<Self, BoxError> {
+    pub fn with_price_trailing(mut self, trailing: Option<f64>) -> Result<Self, Error> {
         match self.order_type {
             OrderKind::TrailingStop => {
                 self.price_trailing = trailing.map(|x| x.to_string());
                 Ok(self)
             }
-            _ => Err("Invalid order type.".into()),
+            _ => Err(Error::InvalidInput("Invalid order type.".into())),
         }
     }
 
-    pub fn with_price_aux_limit(mut self, limit: Option<f64>) -> Result<Self, BoxError> {
+    pub fn with_price_aux_limit(mut self, limit: Option<f64>) -> Result<Self, Error> {
         match self.order_type {
             OrderKind::StopLimit | OrderKind::ExchangeStopLimit => {
                 self.price_aux_limit = limit.map(|x| x.to_string());
                 Ok(self)
             }
-            _ => Err("Invalid order type.".into()),
+            _ => Err(Error::InvalidInput("Invalid order type.".into())),
         }
     }
 
-    pub fn with_price_oco_stop(mut self, oco_stop: Option<f64>) -> Result<Self, BoxError> {
+    pub fn with_price_oco_stop(mut self, oco_stop: Option<f64>) -> Result<Self, Error> {
         match self.flags {
-            None => Err("No flags set.".into()),
+            None => Err(Error::InvalidInput("No flags set.".into())),
             Some(flags) => match OrderFlags::from_bits(flags) {
                 Some(flags) => {
                     if flags.contains(OrderFlags::OCO) {
                         self.price_oco_stop = oco_stop.map(|x| x.to_string());
                         return Ok(self);
                     }
-                    return Err("OCO flag not set.".into());
+                    Err(Error::InvalidInput("OCO flag not set.".into()))
                 }
-                None => Err("OCO flag not set.".into()),
+                None => Err(Error::InvalidInput("OCO flag not set.".into())),
             },
         }
     }
@@ -535,9 +535,117 @@ impl OrderForm {
         self.meta = meta;
         self
     }
+
+    pub(crate) fn cid(&self) -> Option<u32> {
+        self.cid
+    }
+}
+
+/// The changes to apply to an active order, see [`crate::websockets::WebSockets::update_order`].
+#[derive(Serialize, Clone, Debug)]
+pub struct UpdateOrderForm {
+    /// Order ID
+    id: u64,
+    /// Group id for the order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gid: Option<u32>,
+    /// Price of order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<String>,
+    /// Amount of order (positive for buy, negative for sell)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<String>,
+    /// Change of the amount, instead of the new amount
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<String>,
+    /// Set the leverage for a derivative order
+    #[serde(rename = "lev")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leverage: Option<u32>,
+    /// Auxiliary Limit price (for STOP LIMIT)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price_aux_limit: Option<String>,
+    /// The trailing price for a trailing stop order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price_trailing: Option<String>,
+    /// Optional see https://docs.bitfinex.com/v2/docs/flag-values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    /// Time-In-Force: datetime for automatic order cancellation (ie. 2020-01-01 10:45:23) )
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tif: Option<String>,
+}
+
+impl UpdateOrderForm {
+    pub fn new(id: u64) -> Self {
+        UpdateOrderForm {
+            id,
+            gid: None,
+            price: None,
+            amount: None,
+            delta: None,
+            leverage: None,
+            price_aux_limit: None,
+            price_trailing: None,
+            flags: None,
+            tif: None,
+        }
+    }
+
+    pub fn with_gid(mut self, gid: Option<u32>) -> Self {
+        self.gid = gid;
+        self
+    }
+
+    pub fn with_price(mut self, price: Option<f64>) -> Self {
+        self.price = price.map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_amount(mut self, amount: Option<f64>) -> Self {
+        self.amount = amount.map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_delta(mut self, delta: Option<f64>) -> Self {
+        self.delta = delta.map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_leverage(mut self, leverage: Option<u32>) -> Self {
+        self.leverage = leverage;
+        self
+    }
+
+    pub fn with_price_aux_limit(mut self, limit: Option<f64>) -> Self {
+        self.price_aux_limit = limit.map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_price_trailing(mut self, trailing: Option<f64>) -> Self {
+        self.price_trailing = trailing.map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_flags(mut self, flags: Option<OrderFlags>) -> Self {
+        self.flags = flags.map(|x| x.bits());
+        self
+    }
+
+    pub fn with_tif<T: TimeZone>(mut self, tif: Option<DateTime<T>>) -> Self
+    where
+        T::Offset: Display,
+    {
+        self.tif = tif.map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string());
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 pub struct CancelOrderForm {
     #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<u64>,
@@ -565,8 +673,78 @@ impl CancelOrderForm {
             client_id_date: Some(CancelOrderDateTime { date: naive_date }),
         }
     }
+
+    pub(crate) fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    pub(crate) fn client_id(&self) -> Option<u64> {
+        self.client_id
+    }
+}
+
+/// The orders to cancel at once, see [`crate::websockets::WebSockets::cancel_orders_multi`].
+#[derive(Serialize, Clone, Debug)]
+pub struct CancelOrdersForm {
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    ids: Option<Vec<u64>>,
+    #[serde(rename = "gid", skip_serializing_if = "Option::is_none")]
+    group_ids: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    all: Option<u8>,
+}
+
+impl CancelOrdersForm {
+    pub fn from_ids(ids: Vec<u64>) -> Self {
+        CancelOrdersForm {
+            ids: Some(ids),
+            group_ids: None,
+            all: None,
+        }
+    }
+
+    pub fn from_group_ids(group_ids: Vec<u64>) -> Self {
+        CancelOrdersForm {
+            ids: None,
+            group_ids: Some(group_ids),
+            all: None,
+        }
+    }
+
+    /// Cancels every active order of the account.
+    pub fn all() -> Self {
+        CancelOrdersForm {
+            ids: None,
+            group_ids: None,
+            all: Some(1),
+        }
+    }
+}
+
+/// An operation of a batch, see [`crate::websockets::WebSockets::order_multi_op`].
+#[derive(Debug)]
+pub enum OrderOperation {
+    Submit(OrderForm),
+    Update(UpdateOrderForm),
+    Cancel(CancelOrderForm),
+    CancelMulti(CancelOrdersForm),
+}
+
+impl Serialize for OrderOperation {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            OrderOperation::Submit(form) => ("on", form).serialize(serializer),
+            OrderOperation::Update(form) => ("ou", form).serialize(serializer),
+            OrderOperation::Cancel(form) => ("oc", form).serialize(serializer),
+            OrderOperation::CancelMulti(form) => ("oc_multi", form).serialize(serializer),
+        }
+    }
 }
 
+#[derive(Debug)]
 struct CancelOrderDateTime {
     date: NaiveDate,
 }
@@ -596,17 +774,14 @@ impl Orders {
         Orders { client }
     }
 
-    pub async fn active_orders(&self) -> Result<Vec<ActiveOrder>, BoxError> {
+    pub async fn active_orders(&self) -> Result<Vec<ActiveOrder>, Error> {
         let endpoint = AuthenticatedEndpoint::RetrieveOrders;
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 
-    pub async fn history<S: ToString>(
-        &self,
-        symbol: Option<S>,
-    ) -> Result<Vec<ActiveOrder>, BoxError> {
+    pub async fn history<S: ToString>(&self, symbol: Option<S>) -> Result<Vec<ActiveOrder>, Error> {
         let endpoint = match symbol {
             Some(symbol) => AuthenticatedEndpoint::OrdersHistory {
                 symbol: Some(symbol.to_string()),
@@ -617,23 +792,20 @@ impl Orders {
         println!("Endpoint: {:?}", endpoint);
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 
-    pub async fn submit_order(&self, order: &OrderForm) -> Result<OrderResponse, BoxError> {
+    pub async fn submit_order(&self, order: &OrderForm) -> Result<OrderResponse, Error> {
         let endpoint = AuthenticatedEndpoint::SubmitOrder;
         let data = self
             .client
             .post_signed(&endpoint, serde_json::to_string(order)?)
             .await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 
-    pub async fn cancel_order(
-        &self,
-        order_form: &CancelOrderForm,
-    ) -> Result<OrderResponse, BoxError> {
+    pub async fn cancel_order(&self, order_form: &CancelOrderForm) -> Result<OrderResponse, Error> {
         let endpoint = AuthenticatedEndpoint::CancelOrder;
 
         let data = self
@@ -641,6 +813,6 @@ impl Orders {
             .post_signed(&endpoint, serde_json::to_string(order_form)?)
             .await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 }
@@ -1,40 +1,40 @@
-pub static BTCUSD : &'static str = "BTCUSD";
-pub static LTCUSD : &'static str = "LTCUSD";
-pub static LTCBTC : &'static str = "LTCBTC";
-pub static ETHUSD : &'static str = "ETHUSD";
-pub static ETHBTC : &'static str = "ETHBTC";
-pub static ETCUSD : &'static str = "ETCUSD";
-pub static ETCBTC : &'static str = "ETCBTC";
-pub static BFXUSD : &'static str = "BFXUSD";
-pub static BFXBTC : &'static str = "BFXBTC";
-pub static ZECUSD : &'static str = "ZECUSD";
-pub static ZECBTC : &'static str = "ZECBTC";
-pub static XMRUSD : &'static str = "XMRUSD";
-pub static XMRBTC : &'static str = "XMRBTC";
-pub static RRTUSD : &'static str = "RRTUSD";
-pub static RRTBTC : &'static str = "RRTBTC";
-pub static XRPUSD : &'static str = "XRPUSD";
-pub static XRPBTC : &'static str = "XRPBTC";
-pub static EOSETH : &'static str = "EOSETH";
-pub static EOSUSD : &'static str = "EOSUSD";
-pub static EOSBTC : &'static str = "EOSBTC";
-pub static IOTUSD : &'static str = "IOTUSD";
-pub static IOTBTC : &'static str = "IOTBTC";
-pub static IOTETH : &'static str = "IOTETH";
-pub static IOTEUR : &'static str = "IOTEUR";
-pub static BCCBTC : &'static str = "BCCBTC";
-pub static BCUBTC : &'static str = "BCUBTC";
-pub static BCCUSD : &'static str = "BCCUSD";
-pub static BCUUSD : &'static str = "BCUUSD";
-pub static GNTETH : &'static str = "GNTETH";
-pub static GNTUSD : &'static str = "GNTUSD";
-pub static GNTBTC : &'static str = "GNTBTC";
-pub static SANETH : &'static str = "SANETH";
-pub static SANUSD : &'static str = "SANUSD";
-pub static SANBTC : &'static str = "SANBTC";
-pub static AVTETH : &'static str = "AVTETH";
-pub static AVTUSD : &'static str = "AVTUSD";
-pub static AVTBTC : &'static str = "AVTBTC";
-pub static QASHETH : &'static str = "QASHETH";
-pub static QASHUSD : &'static str = "QASHUSD";
-pub static QASHBTC : &'static str = "QASHBTC";
\ No newline at end of file
+pub static BTCUSD: &str = "BTCUSD";
+pub static LTCUSD: &str = "LTCUSD";
+pub static LTCBTC: &str = "LTCBTC";
+pub static ETHUSD: &str = "ETHUSD";
+pub static ETHBTC: &str = "ETHBTC";
+pub static ETCUSD: &str = "ETCUSD";
+pub static ETCBTC: &str = "ETCBTC";
+pub static BFXUSD: &str = "BFXUSD";
+pub static BFXBTC: &str = "BFXBTC";
+pub static ZECUSD: &str = "ZECUSD";
+pub static ZECBTC: &str = "ZECBTC";
+pub static XMRUSD: &str = "XMRUSD";
+pub static XMRBTC: &str = "XMRBTC";
+pub static RRTUSD: &str = "RRTUSD";
+pub static RRTBTC: &str = "RRTBTC";
+pub static XRPUSD: &str = "XRPUSD";
+pub static XRPBTC: &str = "XRPBTC";
+pub static EOSETH: &str = "EOSETH";
+pub static EOSUSD: &str = "EOSUSD";
+pub static EOSBTC: &str = "EOSBTC";
+pub static IOTUSD: &str = "IOTUSD";
+pub static IOTBTC: &str = "IOTBTC";
+pub static IOTETH: &str = "IOTETH";
+pub static IOTEUR: &str = "IOTEUR";
+pub static BCCBTC: &str = "BCCBTC";
+pub static BCUBTC: &str = "BCUBTC";
+pub static BCCUSD: &str = "BCCUSD";
+pub static BCUUSD: &str = "BCUUSD";
+pub static GNTETH: &str = "GNTETH";
+pub static GNTUSD: &str = "GNTUSD";
+pub static GNTBTC: &str = "GNTBTC";
+pub static SANETH: &str = "SANETH";
+pub static SANUSD: &str = "SANUSD";
+pub static SANBTC: &str = "SANBTC";
+pub static AVTETH: &str = "AVTETH";
+pub static AVTUSD: &str = "AVTUSD";
+pub static AVTBTC: &str = "AVTBTC";
+pub static QASHETH: &str = "QASHETH";
+pub static QASHUSD: &str = "QASHUSD";
+pub static QASHBTC: &str = "QASHBTC";
@@ -0,0 +1,549 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::async_websockets::{event_channel, spawn_connection, WebSocketHandle};
+use crate::book::{BookFrequency, BookLength, BookPrecision};
+use crate::candles::CandleKey;
+use crate::config::ClientConfig;
+use crate::errors::Error;
+use crate::events::{NotificationEvent, SubscriptionErrorMessage, WebSocketEvent};
+use crate::websockets::{EventType, Subscription};
+
+// the number of channels Bitfinex allows on a public connection
+static SHARD_CAPACITY: usize = 25;
+static EVENT_BUFFER: usize = 1024;
+
+#[derive(Debug)]
+struct Shard {
+    handle: WebSocketHandle,
+    subscriptions: Vec<Subscription>,
+}
+
+/// Spreads subscriptions over as many websocket connections as needed, each holding at most
+/// 25 channels.
+///
+/// Connections are opened on the current tokio runtime as subscriptions are added. Their events
+/// are merged into a single [`PoolEventStream`], each one tagged with the index of the
+/// connection it was received on. Channel ids are only unique within a connection, so channel
+/// events should be told apart by the subscription of their [`crate::websockets::Channel`].
+/// With a reconnect policy, each connection is re-established on its own. A connection which
+/// stopped is opened again with its subscriptions by [`WebSocketPool::reopen_closed`], or when
+/// it is next subscribed or unsubscribed.
+///
+/// ```no_run
+/// use bitfinex::config::ClientConfig;
+/// use bitfinex::pool::WebSocketPool;
+/// use bitfinex::websockets::EventType;
+/// use futures::StreamExt;
+///
+/// # async fn run() -> Result<(), bitfinex::errors::Error> {
+/// let (mut pool, mut events) = WebSocketPool::new(ClientConfig::default());
+///
+/// for symbol in &["BTCUSD", "ETHUSD", "LTCUSD"] {
+///     pool.subscribe_ticker(*symbol, EventType::Trading).await?;
+/// }
+///
+/// while let Some(event) = events.next().await {
+///     println!("shard {}: {:?}", event.shard, event.event?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct WebSocketPool {
+    config: ClientConfig,
+    capacity: usize,
+    shards: Vec<Shard>,
+    events: mpsc::Sender<PoolEvent>,
+    // the subscriptions rejected by the server, with the index of their shard
+    rejections: Rejections,
+}
+
+#[derive(Debug)]
+struct Rejections {
+    tx: mpsc::UnboundedSender<(usize, SubscriptionErrorMessage)>,
+    rx: mpsc::UnboundedReceiver<(usize, SubscriptionErrorMessage)>,
+}
+
+impl WebSocketPool {
+    /// Creates a pool without any connection, along with the stream of the events of all its
+    /// connections.
+    pub fn new(config: ClientConfig) -> (WebSocketPool, PoolEventStream) {
+        let (events, rx) = mpsc::channel(EVENT_BUFFER);
+        let (rejections_tx, rejections_rx) = mpsc::unbounded_channel();
+
+        let pool = WebSocketPool {
+            config,
+            capacity: SHARD_CAPACITY,
+            shards: Vec::new(),
+            events,
+            rejections: Rejections {
+                tx: rejections_tx,
+                rx: rejections_rx,
+            },
+        };
+
+        (pool, PoolEventStream { rx })
+    }
+
+    /// Sets the maximum number of subscriptions of a connection, 25 by default.
+    pub fn with_shard_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// The number of connections opened so far.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub async fn subscribe_ticker<S>(&mut self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::Ticker { symbol }).await
+    }
+
+    pub async fn subscribe_trades<S>(&mut self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::Trades { symbol }).await
+    }
+
+    /// Subscribes to the candles of `key`.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_candles`].
+    pub async fn subscribe_candles(&mut self, key: CandleKey) -> Result<(), Error> {
+        self.subscribe(Subscription::Candles {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Subscribes to a status channel.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_status`].
+    pub async fn subscribe_status<S>(&mut self, key: S) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        self.subscribe(Subscription::Status { key: key.into() })
+            .await
+    }
+
+    /// Subscribes to a book, failing on an invalid combination of parameters.
+    ///
+    /// See [`crate::websockets::WebSockets::subscribe_books`].
+    pub async fn subscribe_books<S>(
+        &mut self,
+        symbol: S,
+        et: EventType,
+        prec: BookPrecision,
+        freq: BookFrequency,
+        len: BookLength,
+    ) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let subscription = Subscription::books(et.format_symbol(symbol.into()), prec, freq, len)?;
+
+        self.subscribe(subscription).await
+    }
+
+    pub async fn subscribe_raw_books<S>(&mut self, symbol: S, et: EventType) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::RawBooks { symbol }).await
+    }
+
+    /// Subscribes on the first connection with room left, opening a new one if they are all
+    /// full. Fails if `subscription` was already subscribed on any connection.
+    ///
+    /// A subscription rejected by the server is forgotten, freeing its room, once its
+    /// [`WebSocketEvent::SubscriptionError`] went through the event stream.
+    pub async fn subscribe(&mut self, subscription: Subscription) -> Result<(), Error> {
+        self.forget_rejected();
+
+        if self
+            .shards
+            .iter()
+            .any(|shard| shard.subscriptions.contains(&subscription))
+        {
+            return Err(Error::InvalidInput(format!(
+                "Already subscribed to {:?}",
+                subscription
+            )));
+        }
+
+        let capacity = self.capacity;
+
+        let index = match self
+            .shards
+            .iter()
+            .position(|shard| shard.subscriptions.len() < capacity)
+        {
+            Some(index) => index,
+            None => {
+                let handle = self.connect(self.shards.len()).await?;
+
+                self.shards.push(Shard {
+                    handle,
+                    subscriptions: Vec::new(),
+                });
+                self.shards.len() - 1
+            }
+        };
+
+        // recorded first, so it is subscribed again if the connection has to be reopened
+        let shard = &mut self.shards[index];
+        shard.subscriptions.push(subscription.clone());
+
+        if shard.handle.subscribe(subscription.clone()).await.is_err() {
+            if let Err(e) = self.reopen(index).await {
+                self.shards[index]
+                    .subscriptions
+                    .retain(|s| *s != subscription);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unsubscribes from the channel opened by `subscription`, on whichever connection it was
+    /// subscribed. Its room is reused by the next subscription. Fails with an
+    /// [`Error::Protocol`] if it was not subscribed.
+    pub async fn unsubscribe_from(&mut self, subscription: Subscription) -> Result<(), Error> {
+        self.forget_rejected();
+
+        let index = self
+            .shards
+            .iter()
+            .position(|shard| shard.subscriptions.contains(&subscription))
+            .ok_or_else(|| Error::Protocol(format!("Not subscribed to {:?}", subscription)))?;
+
+        let shard = &mut self.shards[index];
+        shard.subscriptions.retain(|s| *s != subscription);
+
+        // a closed connection is reopened without the subscription
+        if shard.handle.unsubscribe_from(subscription).await.is_err()
+            && !shard.subscriptions.is_empty()
+        {
+            self.reopen(index).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Opens again the connections which stopped, e.g. because their reconnect policy gave up,
+    /// and subscribes them to their channels. Returns the number of connections reopened.
+    pub async fn reopen_closed(&mut self) -> Result<usize, Error> {
+        self.forget_rejected();
+
+        let mut reopened = 0;
+
+        for index in 0..self.shards.len() {
+            let shard = &self.shards[index];
+
+            if shard.handle.is_closed() && !shard.subscriptions.is_empty() {
+                self.reopen(index).await?;
+                reopened += 1;
+            }
+        }
+
+        Ok(reopened)
+    }
+
+    // drops the records of the subscriptions the server rejected, the latest matching one being
+    // the rejected request
+    fn forget_rejected(&mut self) {
+        while let Ok((index, error)) = self.rejections.rx.try_recv() {
+            let subscriptions = &mut self.shards[index].subscriptions;
+
+            if let Some(position) = subscriptions.iter().rposition(|s| s.is_rejected_by(&error)) {
+                subscriptions.remove(position);
+            }
+        }
+    }
+
+    // replaces the connection of a shard, replaying its subscriptions
+    async fn reopen(&mut self, index: usize) -> Result<(), Error> {
+        let handle = self.connect(index).await?;
+
+        for subscription in &self.shards[index].subscriptions {
+            handle.subscribe(subscription.clone()).await?;
+        }
+
+        self.shards[index].handle = handle;
+
+        Ok(())
+    }
+
+    // opens a connection whose events are tagged with `shard`
+    async fn connect(&self, shard: usize) -> Result<WebSocketHandle, Error> {
+        let (events, mut stream) = event_channel();
+        let handle = spawn_connection(self.config.clone(), events).await?;
+        let pool_events = self.events.clone();
+        let rejections = self.rejections.tx.clone();
+
+        // dropping the pool stream drops the stream of the connection, which closes it
+        tokio::spawn(async move {
+            while let Some(event) = stream.next().await {
+                // recorded before the event is seen, so the next subscription can take its room
+                if let Ok(WebSocketEvent::SubscriptionError(
+                    NotificationEvent::SubscriptionError(ref error),
+                )) = event
+                {
+                    let _ = rejections.send((shard, error.clone()));
+                }
+
+                if pool_events.send(PoolEvent { shard, event }).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(handle)
+    }
+
+    /// Closes every connection still open. The event stream ends once the pool is dropped.
+    pub async fn close(&self) -> Result<(), Error> {
+        for shard in self.shards.iter().filter(|shard| !shard.handle.is_closed()) {
+            shard.handle.close().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An event received on one of the connections of a [`WebSocketPool`].
+#[derive(Debug)]
+pub struct PoolEvent {
+    /// The index of the connection, from 0 to [`WebSocketPool::shards`] excluded
+    pub shard: usize,
+    /// The event, as yielded by the [`crate::async_websockets::EventStream`] of the connection
+    pub event: Result<WebSocketEvent, Error>,
+}
+
+/// The events of every connection of a [`WebSocketPool`].
+///
+/// A connection which stops, e.g. because its reconnect policy gave up, yields its last error
+/// without ending the stream.
+#[derive(Debug)]
+pub struct PoolEventStream {
+    rx: mpsc::Receiver<PoolEvent>,
+}
+
+impl Stream for PoolEventStream {
+    type Item = PoolEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use tungstenite::Message;
+
+    use super::*;
+
+    /// Accepts websocket connections on a loopback port, greeting each one and reporting the
+    /// frames it receives along with the index of the connection. The first connection drops
+    /// after its first frame.
+    fn server() -> (String, mpsc::UnboundedReceiver<(usize, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let tx = tx.clone();
+
+                thread::spawn(move || {
+                    let mut socket = tungstenite::accept(stream.unwrap()).unwrap();
+                    socket
+                        .write_message(Message::Text(r#"{"event":"info","version":2}"#.into()))
+                        .unwrap();
+
+                    while let Ok(Message::Text(text)) = socket.read_message() {
+                        let _ = tx.send((index, text));
+
+                        if index == 0 {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        (url, rx)
+    }
+
+    /// Accepts a single websocket connection on a loopback port, rejecting every subscription.
+    fn rejecting_server() -> (String, mpsc::UnboundedReceiver<(usize, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+
+            while let Ok(Message::Text(text)) = socket.read_message() {
+                let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+                let error = serde_json::json!({
+                    "event": "error",
+                    "msg": "symbol: invalid",
+                    "code": 10300,
+                    "channel": msg["channel"],
+                    "symbol": msg["symbol"],
+                });
+
+                socket
+                    .write_message(Message::Text(error.to_string()))
+                    .unwrap();
+                let _ = tx.send((0, text));
+            }
+        });
+
+        (url, rx)
+    }
+
+    async fn next_event(events: &mut PoolEventStream) -> PoolEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn next_frame(frames: &mut mpsc::UnboundedReceiver<(usize, String)>) -> (usize, String) {
+        let (index, text) = tokio::time::timeout(Duration::from_secs(5), frames.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let symbol = serde_json::from_str::<serde_json::Value>(&text).unwrap()["symbol"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+
+        (index, symbol)
+    }
+
+    #[tokio::test]
+    async fn pool_reopens_closed_shards() {
+        let (url, mut frames) = server();
+        let (pool, mut events) =
+            WebSocketPool::new(ClientConfig::default().with_websocket_url(url));
+        let mut pool = pool.with_shard_capacity(2);
+
+        pool.subscribe_ticker("BTCUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(next_frame(&mut frames).await, (0, "tBTCUSD".into()));
+
+        let event = next_event(&mut events).await;
+        assert_eq!(event.shard, 0);
+        assert!(matches!(event.event, Ok(WebSocketEvent::Info(_))));
+
+        // without a reconnect policy, the dropped connection stops
+        let event = next_event(&mut events).await;
+        assert_eq!(event.shard, 0);
+        assert!(event.event.is_err());
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !pool.shards[0].handle.is_closed() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        pool.subscribe_ticker("ETHUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(pool.shards(), 1);
+        assert_eq!(next_frame(&mut frames).await, (1, "tBTCUSD".into()));
+        assert_eq!(next_frame(&mut frames).await, (1, "tETHUSD".into()));
+        assert_eq!(next_event(&mut events).await.shard, 0);
+
+        pool.subscribe_ticker("LTCUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(pool.shards(), 2);
+        assert_eq!(next_frame(&mut frames).await, (2, "tLTCUSD".into()));
+        assert_eq!(next_event(&mut events).await.shard, 1);
+    }
+
+    #[tokio::test]
+    async fn pool_rejects_duplicate_subscriptions() {
+        let (url, mut frames) = server();
+        let (mut pool, _events) =
+            WebSocketPool::new(ClientConfig::default().with_websocket_url(url));
+
+        pool.subscribe_ticker("BTCUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(next_frame(&mut frames).await, (0, "tBTCUSD".into()));
+
+        assert!(matches!(
+            pool.subscribe_ticker("BTCUSD", EventType::Trading).await,
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(pool.shards(), 1);
+    }
+
+    #[tokio::test]
+    async fn pool_forgets_rejected_subscriptions() {
+        let (url, mut frames) = rejecting_server();
+        let (pool, mut events) =
+            WebSocketPool::new(ClientConfig::default().with_websocket_url(url));
+        let mut pool = pool.with_shard_capacity(1);
+
+        pool.subscribe_ticker("XXXUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(next_frame(&mut frames).await, (0, "tXXXUSD".into()));
+
+        let event = next_event(&mut events).await;
+        assert_eq!(event.shard, 0);
+        match event.event {
+            Ok(WebSocketEvent::SubscriptionError(NotificationEvent::SubscriptionError(e))) => {
+                assert_eq!(e.code, 10300)
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // the room of the rejected subscription is free, and it can be subscribed again
+        pool.subscribe_ticker("XXXUSD", EventType::Trading)
+            .await
+            .unwrap();
+        assert_eq!(pool.shards(), 1);
+        assert_eq!(next_frame(&mut frames).await, (0, "tXXXUSD".into()));
+
+        assert!(next_event(&mut events).await.event.is_ok());
+        assert!(matches!(
+            pool.unsubscribe_from(Subscription::Ticker {
+                symbol: "tXXXUSD".into()
+            })
+            .await,
+            Err(Error::Protocol(_))
+        ));
+    }
+}
@@ -1,10 +1,8 @@
 use std::collections::HashMap;
 
-use serde_json::from_str;
-
 use crate::client::Client;
 use crate::endpoints::AuthenticatedEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Position {
@@ -14,10 +12,11 @@ pub struct Position {
     base_price: f64,
     margin_funding: f64,
     margin_funding_type: u8,
-    pl: f64,
-    pl_perc: f64,
-    price_liq: f64,
-    leverage: f64,
+    // the profit and loss fields are null in websocket updates, unless computed on request
+    pl: Option<f64>,
+    pl_perc: Option<f64>,
+    price_liq: Option<f64>,
+    leverage: Option<f64>,
     #[serde(skip_serializing)]
     _placeholder1: Option<String>,
     position_id: u64,
@@ -29,7 +28,7 @@ pub struct Position {
     #[serde(skip_serializing)]
     _placeholder3: Option<String>,
     collateral: f64,
-    collateral_min: f64,
+    collateral_min: Option<f64>,
     meta: Option<HashMap<String, serde_json::Value>>,
 }
 
@@ -52,16 +51,16 @@ impl Position {
     pub fn margin_funding_type(&self) -> u8 {
         self.margin_funding_type
     }
-    pub fn pl(&self) -> f64 {
+    pub fn pl(&self) -> Option<f64> {
         self.pl
     }
-    pub fn pl_perc(&self) -> f64 {
+    pub fn pl_perc(&self) -> Option<f64> {
         self.pl_perc
     }
-    pub fn price_liq(&self) -> f64 {
+    pub fn price_liq(&self) -> Option<f64> {
         self.price_liq
     }
-    pub fn leverage(&self) -> f64 {
+    pub fn leverage(&self) -> Option<f64> {
         self.leverage
     }
     pub fn position_id(&self) -> u64 {
@@ -79,7 +78,7 @@ impl Position {
     pub fn collateral(&self) -> f64 {
         self.collateral
     }
-    pub fn collateral_min(&self) -> f64 {
+    pub fn collateral_min(&self) -> Option<f64> {
         self.collateral_min
     }
     pub fn meta(&self) -> &Option<HashMap<String, serde_json::Value>> {
@@ -97,10 +96,10 @@ impl Positions {
         Positions { client }
     }
 
-    pub async fn active_positions(&self) -> Result<Vec<Position>, BoxError> {
+    pub async fn active_positions(&self) -> Result<Vec<Position>, Error> {
         let endpoint = AuthenticatedEndpoint::RetrievePositions;
         let post = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&post)?)
+        from_json(&post)
     }
 }
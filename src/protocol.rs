@@ -0,0 +1,1748 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::book::{BookLength, LocalBook};
+use crate::config::ClientConfig;
+use crate::errors::{from_json, from_value, Error};
+use crate::events::{
+    ConfMessage, DataEvent, FrameInfo, InfoCode, InfoMessage, NotificationEvent, PongMessage,
+    SequenceGap, WebSocketEvent,
+};
+use crate::nonce::NonceProvider;
+use crate::orders::{
+    CancelOrderForm, CancelOrdersForm, OrderForm, OrderOperation, UpdateOrderForm,
+};
+use crate::responses::Notification;
+use crate::signer::{sign_async, Signer};
+use crate::websockets::{Channel, ChannelKind, ConfFlags, Subscription};
+
+static HEARTBEAT: &str = "hb";
+static ACCOUNT_CHANNEL: u64 = 0;
+static CHECKSUM: &str = "cs";
+// te / tu for trading pairs, fte / ftu for funding currencies
+static TRADE_UPDATES: &[&str] = &["te", "tu", "fte", "ftu"];
+static DEAD_MAN_SWITCH_FLAG: u8 = 4;
+static MAX_ORDER_OPERATIONS: usize = 75;
+// how often the watchdog checks the connection
+static WATCHDOG_TICK: Duration = Duration::from_secs(1);
+
+static LAST_CLIENT_ORDER_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The authentication parameters of a connection, kept to authenticate again after a
+/// reconnection.
+#[derive(Clone, Debug)]
+pub(crate) struct Auth {
+    signer: Arc<dyn Signer>,
+    dms: bool,
+    filters: Vec<String>,
+}
+
+impl Auth {
+    pub(crate) fn new(signer: Arc<dyn Signer>, dms: bool, filters: &[&str]) -> Self {
+        Auth {
+            signer,
+            dms,
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    /// Builds the `auth` message, signing a fresh nonce on the calling thread.
+    pub(crate) fn message(&self, nonce_provider: &dyn NonceProvider) -> Result<String, Error> {
+        let (nonce, auth_payload) = Auth::payload(nonce_provider)?;
+        let signature = self.signer.sign(auth_payload.as_bytes())?;
+
+        Ok(self.build(nonce, auth_payload, signature))
+    }
+
+    /// Builds the `auth` message without blocking the runtime on the signer.
+    pub(crate) async fn message_async(
+        &self,
+        nonce_provider: &dyn NonceProvider,
+    ) -> Result<String, Error> {
+        let (nonce, auth_payload) = Auth::payload(nonce_provider)?;
+        let signature = sign_async(&self.signer, auth_payload.clone()).await?;
+
+        Ok(self.build(nonce, auth_payload, signature))
+    }
+
+    fn payload(nonce_provider: &dyn NonceProvider) -> Result<(String, String), Error> {
+        let nonce = nonce_provider.next_nonce()?.to_string();
+        let auth_payload = format!("AUTH{}", nonce);
+
+        Ok((nonce, auth_payload))
+    }
+
+    fn build(&self, nonce: String, auth_payload: String, signature: String) -> String {
+        let msg = json!({
+            "event": "auth",
+            "apiKey": self.signer.api_key(),
+            "authSig": signature,
+            "authNonce": nonce,
+            "authPayload": auth_payload,
+            "dms": if self.dms {Some(DEAD_MAN_SWITCH_FLAG)} else {None},
+            "filters": self.filters,
+        });
+
+        msg.to_string()
+    }
+}
+
+/// Everything sent to set up a connection, replayed after a reconnection.
+#[derive(Debug, Default)]
+pub(crate) struct Session {
+    flags: ConfFlags,
+    auth: Option<Auth>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Session {
+    pub(crate) fn configured(&mut self, flags: ConfFlags) {
+        self.flags = flags;
+    }
+
+    pub(crate) fn authenticated(&mut self, auth: Auth) {
+        self.auth = Some(auth);
+    }
+
+    pub(crate) fn subscribed(&mut self, subscription: Subscription) {
+        if !self.subscriptions.contains(&subscription) {
+            self.subscriptions.push(subscription);
+        }
+    }
+
+    pub(crate) fn auth(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+
+    /// The messages restoring the session on a new connection: configuration and authentication
+    /// first, then every subscription. `auth_message` is the freshly signed message of
+    /// [`Session::auth`], built by the caller so async connections can sign off the runtime.
+    pub(crate) fn restore(&self, auth_message: Option<String>) -> Vec<String> {
+        let mut messages = Vec::new();
+
+        if !self.flags.is_empty() {
+            messages.push(conf_message(self.flags));
+        }
+
+        messages.extend(auth_message);
+        messages.extend(self.subscriptions.iter().map(Subscription::message));
+
+        messages
+    }
+
+    /// Forgets the subscription of `target`, so it is not restored after a reconnection, and
+    /// builds the `unsubscribe` message of its channel.
+    pub(crate) fn unsubscribed(
+        &mut self,
+        target: Unsubscribe,
+        decoder: &Decoder,
+    ) -> Result<String, Error> {
+        let chan_id = match target {
+            Unsubscribe::Channel(chan_id) => {
+                if let Some(channel) = decoder.channel(chan_id) {
+                    self.forget(&channel.subscription);
+                }
+
+                chan_id
+            }
+            Unsubscribe::Subscription(subscription) => {
+                self.forget(&subscription);
+
+                decoder.chan_id(&subscription).ok_or_else(|| {
+                    Error::Protocol(format!("Not subscribed to {:?}", subscription))
+                })?
+            }
+        };
+
+        Ok(unsubscribe_message(chan_id))
+    }
+
+    fn forget(&mut self, subscription: &Subscription) {
+        self.subscriptions.retain(|s| s != subscription);
+    }
+
+    /// The book subscriptions, whose state is stale after a disconnection.
+    pub(crate) fn books(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.iter().filter(|s| s.is_book())
+    }
+}
+
+pub(crate) fn conf_message(flags: ConfFlags) -> String {
+    json!({"event": "conf", "flags": flags.bits()}).to_string()
+}
+
+fn unsubscribe_message(chan_id: u32) -> String {
+    json!({"event": "unsubscribe", "chanId": chan_id}).to_string()
+}
+
+/// A channel to unsubscribe from, by id or by the subscription that opened it.
+#[derive(Debug)]
+pub(crate) enum Unsubscribe {
+    Channel(u32),
+    Subscription(Subscription),
+}
+
+/// How the notification answering an order request is recognised, besides its type.
+#[derive(Debug, PartialEq)]
+enum OrderKey {
+    Id(u64),
+    ClientId(u64),
+    // batches are answered in order
+    Any,
+}
+
+impl OrderKey {
+    // the notification carries the order: [ID, GID, CID, ...]
+    fn matches(&self, info: &Value) -> bool {
+        match self {
+            OrderKey::Id(id) => info[0].as_u64() == Some(*id),
+            OrderKey::ClientId(cid) => info[2].as_u64() == Some(*cid),
+            OrderKey::Any => true,
+        }
+    }
+}
+
+/// An order input message, along with the notification answering it.
+#[derive(Debug)]
+pub(crate) struct OrderMessage {
+    text: String,
+    kind: &'static str,
+    key: OrderKey,
+}
+
+impl OrderMessage {
+    /// Assigns a client id to the order if it has none, to recognise its notification.
+    pub(crate) fn submit(order: &OrderForm) -> Result<Self, Error> {
+        let order = match order.cid() {
+            Some(_) => order.clone(),
+            None => order.clone().with_cid(Some(client_order_id())),
+        };
+        let cid = order.cid().unwrap_or_default();
+
+        OrderMessage::new("on", &order, "on-req", OrderKey::ClientId(cid as u64))
+    }
+
+    pub(crate) fn update(order: &UpdateOrderForm) -> Result<Self, Error> {
+        OrderMessage::new("ou", order, "ou-req", OrderKey::Id(order.id()))
+    }
+
+    pub(crate) fn cancel(order: &CancelOrderForm) -> Result<Self, Error> {
+        let key = match (order.id(), order.client_id()) {
+            (Some(id), _) => OrderKey::Id(id),
+            (None, Some(cid)) => OrderKey::ClientId(cid),
+            (None, None) => OrderKey::Any,
+        };
+
+        OrderMessage::new("oc", order, "oc-req", key)
+    }
+
+    pub(crate) fn cancel_multi(orders: &CancelOrdersForm) -> Result<Self, Error> {
+        OrderMessage::new("oc_multi", orders, "oc_multi-req", OrderKey::Any)
+    }
+
+    pub(crate) fn multi_op(operations: &[OrderOperation]) -> Result<Self, Error> {
+        if operations.is_empty() || operations.len() > MAX_ORDER_OPERATIONS {
+            return Err(Error::InvalidInput(format!(
+                "Between 1 and {} operations can be sent at once",
+                MAX_ORDER_OPERATIONS
+            )));
+        }
+
+        OrderMessage::new("ox_multi", &operations, "ox_multi-req", OrderKey::Any)
+    }
+
+    fn new<T: Serialize>(
+        input: &str,
+        form: &T,
+        kind: &'static str,
+        key: OrderKey,
+    ) -> Result<Self, Error> {
+        Ok(OrderMessage {
+            text: serde_json::to_string(&(0, input, (), form))?,
+            kind,
+            key,
+        })
+    }
+
+    /// Splits the message into the text to send, the request to track until its notification is
+    /// received, and the receiver of that notification.
+    pub(crate) fn into_parts(self) -> (String, PendingOrder, oneshot::Receiver<OrderReply>) {
+        let (reply, rx) = oneshot::channel();
+        let pending = PendingOrder {
+            kind: self.kind,
+            key: self.key,
+            reply,
+        };
+
+        (self.text, pending, rx)
+    }
+}
+
+/// The notification answering an order request, or the reason it will not be answered.
+pub(crate) type OrderReply = Result<Notification, Error>;
+
+#[derive(Debug)]
+pub(crate) struct PendingOrder {
+    kind: &'static str,
+    key: OrderKey,
+    reply: oneshot::Sender<OrderReply>,
+}
+
+impl PendingOrder {
+    fn fail(self, reason: &str) {
+        let _ = self.reply.send(Err(Error::Protocol(reason.to_owned())));
+    }
+}
+
+/// The order requests sent on a connection, waiting for their notification, and the ones held
+/// during a maintenance.
+#[derive(Debug)]
+pub(crate) struct OrderRequests {
+    pending: Vec<PendingOrder>,
+    held: Vec<(String, PendingOrder)>,
+    // why orders are not sent: the connection was never authenticated, or authentication failed
+    refused: Option<String>,
+}
+
+impl Default for OrderRequests {
+    fn default() -> Self {
+        OrderRequests {
+            pending: Vec::new(),
+            held: Vec::new(),
+            refused: Some("Orders require an authenticated connection".into()),
+        }
+    }
+}
+
+impl OrderRequests {
+    /// Accepts orders from now on, they are sent after the `auth` message.
+    pub(crate) fn authenticating(&mut self) {
+        self.refused = None;
+    }
+
+    /// Returns the message to send, or holds it until `release` if `hold` is set. Without
+    /// authentication, the request fails right away since the server would never answer it.
+    pub(crate) fn send(
+        &mut self,
+        text: String,
+        pending: PendingOrder,
+        hold: bool,
+    ) -> Option<String> {
+        if let Some(ref reason) = self.refused {
+            pending.fail(reason);
+            return None;
+        }
+
+        if hold {
+            self.held.push((text, pending));
+            return None;
+        }
+
+        self.pending.push(pending);
+        Some(text)
+    }
+
+    /// The messages of the held requests, to send now.
+    pub(crate) fn release(&mut self) -> Vec<String> {
+        let held = std::mem::take(&mut self.held);
+
+        held.into_iter()
+            .map(|(text, pending)| {
+                self.pending.push(pending);
+                text
+            })
+            .collect()
+    }
+
+    /// Resolves the oldest request answered by the event, if it is a notification.
+    ///
+    /// An `error` event which is not about a channel fails the oldest pending request, and a
+    /// failed authentication fails every request.
+    pub(crate) fn resolve(&mut self, event: &WebSocketEvent) {
+        let notification = match event {
+            WebSocketEvent::Account(DataEvent::AccountNotificationEvent(n), _) => n,
+            WebSocketEvent::Auth(NotificationEvent::Auth(auth)) if !auth.is_ok() => {
+                let reason = format!(
+                    "Authentication failed: {}",
+                    auth.msg.as_deref().unwrap_or(&auth.status)
+                );
+
+                for pending in self.pending.drain(..) {
+                    pending.fail(&reason);
+                }
+
+                for (_, pending) in self.held.drain(..) {
+                    pending.fail(&reason);
+                }
+
+                self.refused = Some(reason);
+                return;
+            }
+            WebSocketEvent::SubscriptionError(NotificationEvent::SubscriptionError(e))
+                if e.channel.is_none() && e.chan_id.is_none() =>
+            {
+                if !self.pending.is_empty() {
+                    self.pending
+                        .remove(0)
+                        .fail(&format!("{} ({})", e.msg, e.code));
+                }
+
+                return;
+            }
+            _ => return,
+        };
+
+        let position = self.pending.iter().position(|p| {
+            p.kind == notification.notification_type() && p.key.matches(notification.info())
+        });
+
+        if let Some(position) = position {
+            let pending = self.pending.remove(position);
+            let _ = pending.reply.send(Ok(notification.clone()));
+        }
+    }
+
+    /// Drops every pending request, their notification is lost with the connection. The held
+    /// requests were never sent, they are kept for the next connection.
+    pub(crate) fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+// client ids must be unique in the day, the milliseconds since midnight are used
+fn client_order_id() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_millis() % 86_400_000) as u32)
+        .unwrap_or(0);
+
+    let last = LAST_CLIENT_ORDER_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(cmp::max(last + 1, now))
+        })
+        .unwrap_or(now);
+
+    cmp::max(last + 1, now)
+}
+
+/// Whether the error means the connection is lost, rather than a message being invalid.
+pub(crate) fn is_connection_error(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::WebSocket(_) | Error::Io(_) | Error::Disconnected(_)
+    )
+}
+
+/// Fails connections whose channels went silent, and pings them to detect half-open sockets,
+/// see [`ClientConfig::with_heartbeat_timeout`] and [`ClientConfig::with_ping_interval`].
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    heartbeat_timeout: Option<Duration>,
+    ping_interval: Option<Duration>,
+    last_ping: Instant,
+    // the cid of the ping awaiting its pong
+    pending: Option<u64>,
+    cid: u64,
+}
+
+impl Watchdog {
+    pub(crate) fn new(config: &ClientConfig) -> Self {
+        Watchdog {
+            heartbeat_timeout: config.heartbeat_timeout(),
+            ping_interval: config.ping_interval(),
+            last_ping: Instant::now(),
+            pending: None,
+            cid: 0,
+        }
+    }
+
+    /// How often `check` must be called, if anything is checked.
+    pub(crate) fn tick(config: &ClientConfig) -> Option<Duration> {
+        match (config.heartbeat_timeout(), config.ping_interval()) {
+            (None, None) => None,
+            _ => Some(WATCHDOG_TICK),
+        }
+    }
+
+    /// Starts over on a new connection.
+    pub(crate) fn reset(&mut self) {
+        self.last_ping = Instant::now();
+        self.pending = None;
+    }
+
+    /// Fails if a channel or the socket went silent. Returns the ping to send, if one is due.
+    pub(crate) fn check(&mut self, decoder: &Decoder) -> Result<Option<String>, Error> {
+        if let Some(timeout) = self.heartbeat_timeout {
+            if let Some(chan_id) = decoder.stale_channel(timeout) {
+                return Err(Error::Disconnected(format!(
+                    "No heartbeat on channel {} for {:?}",
+                    chan_id, timeout
+                )));
+            }
+        }
+
+        let interval = match self.ping_interval {
+            Some(interval) if self.last_ping.elapsed() >= interval => interval,
+            _ => return Ok(None),
+        };
+
+        if let Some(cid) = self.pending {
+            if decoder.pong.map(|pong| pong < cid).unwrap_or(true) {
+                return Err(Error::Disconnected(format!(
+                    "No pong within {:?} of ping {}",
+                    interval, cid
+                )));
+            }
+        }
+
+        self.cid += 1;
+        self.pending = Some(self.cid);
+        self.last_ping = Instant::now();
+
+        Ok(Some(json!({"event": "ping", "cid": self.cid}).to_string()))
+    }
+}
+
+/// Parses text frames, routing channel data by the subscription of its channel.
+///
+/// Once checksums are enabled, a copy of every trading book is maintained to verify them. Once
+/// sequence numbers are enabled, skipped numbers are reported.
+#[derive(Debug, Default)]
+pub(crate) struct Decoder {
+    channels: HashMap<u32, Channel>,
+    // the flags confirmed by the server
+    flags: ConfFlags,
+    // channels which received data, the first data of a book is its snapshot
+    received: HashSet<u32>,
+    seq: Option<u64>,
+    auth_seq: Option<u64>,
+    // the last frame of every channel, heartbeats included
+    seen: HashMap<u32, Instant>,
+    // the cid of the last pong
+    pong: Option<u64>,
+    maintenance: bool,
+    reconnect_requested: bool,
+    books: HashMap<u32, LocalBook>,
+    // books unsubscribed after a checksum mismatch, subscribed again once confirmed
+    resubscriptions: HashMap<u32, Subscription>,
+    replies: Vec<String>,
+}
+
+impl Decoder {
+    pub(crate) fn channel(&self, chan_id: u32) -> Option<&Channel> {
+        self.channels.get(&chan_id)
+    }
+
+    /// The id of the channel opened by `subscription`, once confirmed.
+    pub(crate) fn chan_id(&self, subscription: &Subscription) -> Option<u32> {
+        self.channels
+            .values()
+            .find(|channel| channel.subscription == *subscription)
+            .map(|channel| channel.chan_id)
+    }
+
+    /// The messages to send in reaction to the decoded frames, e.g. to resubscribe a book whose
+    /// checksum mismatched.
+    pub(crate) fn take_replies(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.replies)
+    }
+
+    /// Whether the platform is in maintenance, orders are held meanwhile.
+    pub(crate) fn maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    /// Whether the server asked to reconnect.
+    pub(crate) fn reconnect_requested(&self) -> bool {
+        self.reconnect_requested
+    }
+
+    /// The first channel which received nothing for `timeout`.
+    pub(crate) fn stale_channel(&self, timeout: Duration) -> Option<u32> {
+        self.seen
+            .iter()
+            .find(|(_, seen)| seen.elapsed() >= timeout)
+            .map(|(chan_id, _)| *chan_id)
+    }
+
+    /// Forgets every channel, their ids are only valid for the lifetime of a connection.
+    pub(crate) fn reset(&mut self) {
+        *self = Decoder::default();
+    }
+
+    /// Parses a text frame into its events: its data, preceded by a [`WebSocketEvent::SequenceGap`]
+    /// if frames were lost. Heartbeats yield no data.
+    ///
+    /// Objects are events, keyed on their `event` field. Arrays are channel data, keyed on their
+    /// channel id and message type. Anything else is returned as [`WebSocketEvent::Unknown`].
+    /// Fails on frames which cannot be parsed, and on subscriptions whose channel cannot be
+    /// tracked.
+    pub(crate) fn decode(&mut self, text: &str) -> Result<Vec<WebSocketEvent>, Error> {
+        let event = match from_json(text)? {
+            Value::Array(frame) => return self.channel_events(frame, text),
+            msg @ Value::Object(_) => self.event(msg, text)?,
+            _ => None,
+        };
+
+        Ok(vec![event.unwrap_or_else(|| {
+            WebSocketEvent::Unknown(text.to_owned())
+        })])
+    }
+
+    fn channel_events(
+        &mut self,
+        mut frame: Vec<Value>,
+        text: &str,
+    ) -> Result<Vec<WebSocketEvent>, Error> {
+        let info = self.frame_info(&mut frame);
+        let mut events = self.sequence_gaps(&info);
+
+        if let Some(chan_id) = frame.first().and_then(Value::as_u64) {
+            if let Some(seen) = self.seen.get_mut(&(chan_id as u32)) {
+                *seen = Instant::now();
+            }
+        }
+
+        if frame.get(1).and_then(Value::as_str) == Some(HEARTBEAT) {
+            return Ok(events);
+        }
+
+        let chan_id = frame.first().and_then(Value::as_u64);
+
+        let event = if chan_id == Some(ACCOUNT_CHANNEL) {
+            account_data(frame, text)?.map(|event| WebSocketEvent::Account(event, info))
+        } else {
+            match chan_id.and_then(|chan_id| self.channels.get(&(chan_id as u32)).cloned()) {
+                Some(channel) => {
+                    let first = self.received.insert(channel.chan_id);
+
+                    channel_data(&channel, frame, text, first)?
+                        .map(|event| self.track_book(channel, event, info))
+                }
+                None => None,
+            }
+        };
+
+        events.push(event.unwrap_or_else(|| WebSocketEvent::Unknown(text.to_owned())));
+
+        Ok(events)
+    }
+
+    // removes the timestamp and sequence numbers trailing the frame, the sequence numbers last
+    fn frame_info(&self, frame: &mut Vec<Value>) -> FrameInfo {
+        let mut info = FrameInfo::default();
+
+        if self.flags.contains(ConfFlags::SEQ_ALL) {
+            let account = frame.first().and_then(Value::as_u64) == Some(ACCOUNT_CHANNEL)
+                && frame.get(1).and_then(Value::as_str) != Some(HEARTBEAT);
+
+            if account {
+                info.auth_seq = pop_number(frame);
+            }
+
+            info.seq = pop_number(frame);
+        }
+
+        if self.flags.contains(ConfFlags::TIMESTAMP) {
+            info.timestamp = pop_number(frame);
+        }
+
+        info
+    }
+
+    fn sequence_gaps(&mut self, info: &FrameInfo) -> Vec<WebSocketEvent> {
+        let mut gaps = Vec::new();
+
+        for (last, received, account) in [
+            (&mut self.seq, info.seq, false),
+            (&mut self.auth_seq, info.auth_seq, true),
+        ] {
+            let received = match received {
+                Some(received) => received,
+                None => continue,
+            };
+
+            if let Some(expected) = last.map(|last| last + 1) {
+                if received != expected {
+                    gaps.push(WebSocketEvent::SequenceGap(SequenceGap {
+                        expected,
+                        received,
+                        account,
+                    }));
+                }
+            }
+
+            *last = Some(received);
+        }
+
+        gaps
+    }
+
+    fn event(&mut self, msg: Value, text: &str) -> Result<Option<WebSocketEvent>, Error> {
+        let event = match msg["event"].as_str() {
+            Some("info") => {
+                let info: InfoMessage = from_value(msg, text)?;
+
+                if let Some(maintenance) = info.is_maintenance() {
+                    if self.maintenance && !maintenance {
+                        self.resubscribe_all();
+                    }
+
+                    self.maintenance = maintenance;
+                }
+
+                if info.info_code() == Some(InfoCode::ReconnectRequested) {
+                    self.reconnect_requested = true;
+                }
+
+                WebSocketEvent::Info(NotificationEvent::Info(info))
+            }
+            Some("auth") => {
+                // the heartbeats of the account channel start once authenticated
+                if msg["status"].as_str() == Some("OK") {
+                    self.seen.insert(ACCOUNT_CHANNEL as u32, Instant::now());
+                }
+
+                WebSocketEvent::Auth(from_value(msg, text)?)
+            }
+            Some("subscribed") => {
+                // otherwise the data of the channel would silently be unknown
+                let channel = subscribed_channel(&msg).ok_or_else(|| {
+                    Error::Protocol(format!(
+                        "Unexpected subscription of channel {}: {}",
+                        msg["chanId"], text
+                    ))
+                })?;
+
+                self.seen.insert(channel.chan_id, Instant::now());
+                self.channels.insert(channel.chan_id, channel);
+
+                if msg["channel"].as_str() == Some("status") {
+                    WebSocketEvent::Subscribed(NotificationEvent::StatusSubscribed(from_value(
+                        msg, text,
+                    )?))
+                } else {
+                    WebSocketEvent::Subscribed(from_value(msg, text)?)
+                }
+            }
+            Some("unsubscribed") => {
+                if let Some(chan_id) = msg["chanId"].as_u64() {
+                    let chan_id = chan_id as u32;
+
+                    self.channels.remove(&chan_id);
+                    self.received.remove(&chan_id);
+                    self.seen.remove(&chan_id);
+                    self.books.remove(&chan_id);
+
+                    if let Some(subscription) = self.resubscriptions.remove(&chan_id) {
+                        self.replies.push(subscription.message());
+                    }
+                }
+
+                WebSocketEvent::Unsubscribed(NotificationEvent::Unsubscribed(from_value(
+                    msg, text,
+                )?))
+            }
+            Some("pong") => {
+                let pong: PongMessage = from_value(msg, text)?;
+                self.pong = Some(pong.cid);
+
+                WebSocketEvent::Pong(NotificationEvent::Pong(pong))
+            }
+            Some("error") => WebSocketEvent::SubscriptionError(
+                NotificationEvent::SubscriptionError(from_value(msg, text)?),
+            ),
+            Some("conf") => {
+                let conf: ConfMessage = from_value(msg, text)?;
+
+                if conf.is_ok() {
+                    self.flags = ConfFlags::from_bits_truncate(conf.flags.unwrap_or_default());
+                }
+
+                WebSocketEvent::Configured(NotificationEvent::Conf(conf))
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(event))
+    }
+
+    // unsubscribes every channel, each is subscribed again once its unsubscription is confirmed
+    fn resubscribe_all(&mut self) {
+        for (chan_id, channel) in &self.channels {
+            self.replies.push(unsubscribe_message(*chan_id));
+            self.resubscriptions
+                .insert(*chan_id, channel.subscription.clone());
+        }
+
+        self.books.clear();
+    }
+
+    // maintains the local trading books and verifies their checksums
+    fn track_book(
+        &mut self,
+        channel: Channel,
+        event: DataEvent,
+        info: FrameInfo,
+    ) -> WebSocketEvent {
+        if !self.flags.contains(ConfFlags::OB_CHECKSUM) || channel.subscription.is_funding() {
+            return WebSocketEvent::Data(channel, event, info);
+        }
+
+        let chan_id = channel.chan_id;
+
+        match event {
+            DataEvent::BookTradingSnapshotEvent(_, ref levels) => {
+                let book = self.books.entry(chan_id).or_default();
+                *book = LocalBook::default();
+                levels.iter().for_each(|level| book.update_level(level));
+            }
+            DataEvent::BookTradingUpdateEvent(_, ref level) => {
+                if let Some(book) = self.books.get_mut(&chan_id) {
+                    book.update_level(level);
+                }
+            }
+            DataEvent::BookTradingBulkUpdateEvent(_, ref levels) => {
+                if let Some(book) = self.books.get_mut(&chan_id) {
+                    levels.iter().for_each(|level| book.update_level(level));
+                }
+            }
+            DataEvent::RawBookUpdateEvent(_, ref orders) => {
+                let book = self.books.entry(chan_id).or_default();
+                *book = LocalBook::default();
+                orders.iter().for_each(|order| book.update_order(order));
+            }
+            DataEvent::RawBookEvent(_, ref order) => {
+                if let Some(book) = self.books.get_mut(&chan_id) {
+                    book.update_order(order);
+                }
+            }
+            DataEvent::RawBookBulkUpdateEvent(_, ref orders) => {
+                if let Some(book) = self.books.get_mut(&chan_id) {
+                    orders.iter().for_each(|order| book.update_order(order));
+                }
+            }
+            DataEvent::BookChecksumEvent(_, checksum) => {
+                let mismatch = self
+                    .books
+                    .get(&chan_id)
+                    .map(|book| book.checksum() != checksum)
+                    .unwrap_or(false);
+
+                if mismatch {
+                    self.books.remove(&chan_id);
+                    self.replies.push(unsubscribe_message(chan_id));
+                    self.resubscriptions
+                        .insert(chan_id, channel.subscription.clone());
+
+                    return WebSocketEvent::ChecksumMismatch(channel);
+                }
+            }
+            _ => {}
+        }
+
+        WebSocketEvent::Data(channel, event, info)
+    }
+}
+
+// keeps the channel id and the message type, or payload
+fn pop_number(frame: &mut Vec<Value>) -> Option<u64> {
+    match frame.last() {
+        Some(Value::Number(_)) if frame.len() > 2 => frame.pop().and_then(|n| n.as_u64()),
+        _ => None,
+    }
+}
+
+fn subscribed_channel(msg: &Value) -> Option<Channel> {
+    let chan_id = msg["chanId"].as_u64()? as u32;
+    let symbol = || msg["symbol"].as_str().map(String::from);
+
+    let subscription = match msg["channel"].as_str()? {
+        "ticker" => Subscription::Ticker { symbol: symbol()? },
+        "trades" => Subscription::Trades { symbol: symbol()? },
+        "candles" => Subscription::Candles {
+            key: msg["key"].as_str()?.to_owned(),
+        },
+        "status" => Subscription::Status {
+            key: msg["key"].as_str()?.to_owned(),
+        },
+        "book" => match msg["prec"].as_str()? {
+            "R0" => Subscription::RawBooks { symbol: symbol()? },
+            prec => Subscription::Books {
+                symbol: symbol()?,
+                prec: prec.parse().ok()?,
+                freq: msg["freq"].as_str()?.parse().ok()?,
+                // sent back as a string
+                len: BookLength::try_from(match msg["len"] {
+                    Value::String(ref len) => len.parse().ok()?,
+                    ref len => len.as_u64()? as u32,
+                })
+                .ok()?,
+            },
+        },
+        _ => return None,
+    };
+
+    Some(Channel {
+        chan_id,
+        subscription,
+    })
+}
+
+// None for the message types which are not modelled
+fn channel_data(
+    channel: &Channel,
+    mut frame: Vec<Value>,
+    text: &str,
+    first: bool,
+) -> Result<Option<DataEvent>, Error> {
+    let chan_id = channel.chan_id as i32;
+    let funding = channel.subscription.is_funding();
+
+    if frame.len() < 2 {
+        return Ok(None);
+    }
+
+    let payload = match frame.remove(1) {
+        Value::String(kind) => {
+            let entry = match frame.pop() {
+                Some(entry) if frame.len() == 1 => entry,
+                _ => return Ok(None),
+            };
+
+            let event = match channel.kind() {
+                ChannelKind::Book | ChannelKind::RawBook if kind == CHECKSUM => {
+                    DataEvent::BookChecksumEvent(chan_id, from_value(entry, text)?)
+                }
+                ChannelKind::Trades if TRADE_UPDATES.contains(&kind.as_str()) => {
+                    if funding {
+                        DataEvent::TradesFundingUpdateEvent(chan_id, kind, from_value(entry, text)?)
+                    } else {
+                        DataEvent::TradesTradingUpdateEvent(chan_id, kind, from_value(entry, text)?)
+                    }
+                }
+                _ => return Ok(None),
+            };
+
+            return Ok(Some(event));
+        }
+        payload => payload,
+    };
+
+    // snapshots are arrays of entries, updates a single entry. Bulk updates are arrays of entries
+    // as well, received after the snapshot of a book
+    let snapshot = payload
+        .as_array()
+        .map(|entries| entries.first().map(Value::is_array).unwrap_or(true))
+        .unwrap_or(false);
+
+    let event = match channel.kind() {
+        ChannelKind::Ticker if funding => {
+            DataEvent::TickerFundingEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::Ticker => DataEvent::TickerTradingEvent(chan_id, from_value(payload, text)?),
+        ChannelKind::Trades if funding => {
+            DataEvent::TradesFundingSnapshotEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::Trades => {
+            DataEvent::TradesTradingSnapshotEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::Book if snapshot && !first => {
+            if funding {
+                DataEvent::BookFundingBulkUpdateEvent(chan_id, from_value(payload, text)?)
+            } else {
+                DataEvent::BookTradingBulkUpdateEvent(chan_id, from_value(payload, text)?)
+            }
+        }
+        ChannelKind::Book => match (funding, snapshot) {
+            (true, true) => {
+                DataEvent::BookFundingSnapshotEvent(chan_id, from_value(payload, text)?)
+            }
+            (true, false) => DataEvent::BookFundingUpdateEvent(chan_id, from_value(payload, text)?),
+            (false, true) => {
+                DataEvent::BookTradingSnapshotEvent(chan_id, from_value(payload, text)?)
+            }
+            (false, false) => {
+                DataEvent::BookTradingUpdateEvent(chan_id, from_value(payload, text)?)
+            }
+        },
+        ChannelKind::RawBook if snapshot && !first => {
+            DataEvent::RawBookBulkUpdateEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::RawBook if snapshot => {
+            DataEvent::RawBookUpdateEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::RawBook => DataEvent::RawBookEvent(chan_id, from_value(payload, text)?),
+        ChannelKind::Candles if snapshot => {
+            DataEvent::CandlesSnapshotEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::Candles => DataEvent::CandlesUpdateEvent(chan_id, from_value(payload, text)?),
+        // liquidations are always sent as arrays of entries
+        ChannelKind::Status if channel.subscription.is_liquidations() => {
+            if first {
+                DataEvent::LiquidationSnapshotEvent(chan_id, from_value(payload, text)?)
+            } else {
+                DataEvent::LiquidationUpdateEvent(chan_id, from_value(payload, text)?)
+            }
+        }
+        // the derivative status is sent whole, the first one being the snapshot
+        ChannelKind::Status if first => {
+            DataEvent::DerivativeStatusSnapshotEvent(chan_id, from_value(payload, text)?)
+        }
+        ChannelKind::Status => {
+            DataEvent::DerivativeStatusUpdateEvent(chan_id, from_value(payload, text)?)
+        }
+    };
+
+    Ok(Some(event))
+}
+
+// None for the message types which are not modelled
+fn account_data(mut frame: Vec<Value>, text: &str) -> Result<Option<DataEvent>, Error> {
+    let payload = match frame.pop() {
+        Some(payload) if frame.len() == 2 => payload,
+        _ => return Ok(None),
+    };
+
+    let event = match frame[1].as_str().unwrap_or_default() {
+        "os" => DataEvent::OrderSnapshotEvent(from_value(payload, text)?),
+        "on" => DataEvent::OrderNewEvent(from_value(payload, text)?),
+        "ou" => DataEvent::OrderUpdateEvent(from_value(payload, text)?),
+        "oc" => DataEvent::OrderCancelEvent(from_value(payload, text)?),
+        "ps" => DataEvent::PositionSnapshotEvent(from_value(payload, text)?),
+        "pn" => DataEvent::PositionNewEvent(from_value(payload, text)?),
+        "pu" => DataEvent::PositionUpdateEvent(from_value(payload, text)?),
+        "pc" => DataEvent::PositionCloseEvent(from_value(payload, text)?),
+        "ws" => DataEvent::WalletSnapshotEvent(from_value(payload, text)?),
+        "wu" => DataEvent::WalletUpdateEvent(from_value(payload, text)?),
+        "te" => DataEvent::TradeExecutedEvent(from_value(payload, text)?),
+        "tu" => DataEvent::TradeExecutionUpdateEvent(from_value(payload, text)?),
+        "bu" => DataEvent::BalanceUpdateEvent(from_value(payload, text)?),
+        "miu" => match payload.get(0).and_then(Value::as_str) {
+            Some("base") => DataEvent::MarginBaseUpdateEvent(from_value(payload, text)?),
+            Some("sym") => DataEvent::MarginSymbolUpdateEvent(from_value(payload, text)?),
+            _ => return Ok(None),
+        },
+        "fiu" => DataEvent::FundingInfoUpdateEvent(from_value(payload, text)?),
+        "n" => DataEvent::AccountNotificationEvent(from_value(payload, text)?),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orders::OrderKind;
+
+    static TICKER_SUBSCRIBED: &str = r#"{"event":"subscribed","channel":"ticker","chanId":1,"symbol":"tBTCUSD","pair":"BTCUSD"}"#;
+    static TRADES_SUBSCRIBED: &str = r#"{"event":"subscribed","channel":"trades","chanId":2,"symbol":"tBTCUSD","pair":"BTCUSD"}"#;
+    static CANDLES_SUBSCRIBED: &str =
+        r#"{"event":"subscribed","channel":"candles","chanId":3,"key":"trade:1m:tBTCUSD"}"#;
+
+    // decodes a frame expected to yield a single event
+    fn decode_one(decoder: &mut Decoder, text: &str) -> WebSocketEvent {
+        let mut events = decoder.decode(text).unwrap();
+        assert_eq!(events.len(), 1, "{:?}", events);
+        events.remove(0)
+    }
+
+    fn trades(symbol: &str) -> Subscription {
+        Subscription::Trades {
+            symbol: symbol.into(),
+        }
+    }
+
+    #[test]
+    fn subscribed_channels_are_registered() {
+        let mut decoder = Decoder::default();
+
+        for text in &[TICKER_SUBSCRIBED, TRADES_SUBSCRIBED, CANDLES_SUBSCRIBED] {
+            match decode_one(&mut decoder, text) {
+                WebSocketEvent::Subscribed(_) => {}
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+
+        assert_eq!(
+            decoder.channel(2),
+            Some(&Channel {
+                chan_id: 2,
+                subscription: trades("tBTCUSD"),
+            })
+        );
+        assert_eq!(decoder.chan_id(&trades("tBTCUSD")), Some(2));
+        assert_eq!(
+            decoder.channel(3).map(Channel::kind),
+            Some(ChannelKind::Candles)
+        );
+        assert_eq!(decoder.channel(4), None);
+    }
+
+    #[test]
+    fn data_is_dispatched_to_the_kind_of_its_channel() {
+        let mut decoder = Decoder::default();
+
+        for text in &[TICKER_SUBSCRIBED, TRADES_SUBSCRIBED, CANDLES_SUBSCRIBED] {
+            decoder.decode(text).unwrap();
+        }
+
+        let frames = [
+            (
+                r#"[1,[7254.6,38.2,7254.7,53.3,-95.9,-0.0131,7254.7,4185.6,7379.8,7201]]"#,
+                1,
+                "TickerTradingEvent",
+            ),
+            (
+                r#"[2,[[401597395,1574694475039,0.005,7244.9]]]"#,
+                2,
+                "TradesTradingSnapshotEvent",
+            ),
+            (
+                r#"[2,"te",[401597396,1574694478808,0.005,7245.3]]"#,
+                2,
+                "TradesTradingUpdateEvent",
+            ),
+            (
+                r#"[3,[[1574698260000,7379.8,7379.8,7379.8,7379.8,0.00683335]]]"#,
+                3,
+                "CandlesSnapshotEvent",
+            ),
+            (
+                r#"[3,[1574698320000,7379.8,7380,7380,7379.8,0.1]]"#,
+                3,
+                "CandlesUpdateEvent",
+            ),
+        ];
+
+        for (text, chan_id, kind) in frames.iter() {
+            match decode_one(&mut decoder, text) {
+                WebSocketEvent::Data(channel, event, _) => {
+                    assert_eq!(channel.chan_id, *chan_id);
+                    assert!(format!("{:?}", event).starts_with(kind), "{:?}", event);
+                }
+                event => panic!("unexpected event {:?} for {}", event, text),
+            }
+        }
+    }
+
+    #[test]
+    fn status_data_is_a_snapshot_then_updates() {
+        let mut decoder = Decoder::default();
+        decoder
+            .decode(r#"{"event":"subscribed","channel":"status","chanId":4,"key":"deriv:tBTCF0:USTF0"}"#)
+            .unwrap();
+        decoder
+            .decode(r#"{"event":"subscribed","channel":"status","chanId":5,"key":"liq:global"}"#)
+            .unwrap();
+
+        let frames = [
+            (
+                r#"[4,[1609144353000,null,26500.5,26495.2,null,1123456.7,null,1609156800000,0.00001234,240,null,0.0001,null,null,26501.1,null,null,1234.5,null,null,null,-0.005,0.005]]"#,
+                "DerivativeStatusSnapshotEvent",
+            ),
+            (
+                r#"[4,[1609144354000,null,26501.5,26495.9,null,1123456.7,null,1609156800000,0.00001301,241,null,0.0001,null,null,26502,null,null,1234.5,null,null,null,-0.005,0.005]]"#,
+                "DerivativeStatusUpdateEvent",
+            ),
+            (
+                r#"[5,[["pos",145400868,1609144352338,null,"tETHF0:USTF0",-1.67566173,736.96,null,1,1,null,736.96]]]"#,
+                "LiquidationSnapshotEvent",
+            ),
+            (
+                r#"[5,[["pos",145400869,1609144352939,null,"tBTCF0:USTF0",0.01,26400,null,0,1,null,26310]]]"#,
+                "LiquidationUpdateEvent",
+            ),
+        ];
+
+        for (text, kind) in frames.iter() {
+            match decode_one(&mut decoder, text) {
+                WebSocketEvent::Data(_, event, _) => {
+                    assert!(format!("{:?}", event).starts_with(kind), "{:?}", event)
+                }
+                event => panic!("unexpected event {:?} for {}", event, text),
+            }
+        }
+    }
+
+    #[test]
+    fn unsubscribed_channels_are_forgotten() {
+        let mut decoder = Decoder::default();
+        decoder.decode(TRADES_SUBSCRIBED).unwrap();
+
+        match decode_one(
+            &mut decoder,
+            r#"{"event":"unsubscribed","status":"OK","chanId":2}"#,
+        ) {
+            WebSocketEvent::Unsubscribed(_) => {}
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        assert_eq!(decoder.channel(2), None);
+        assert_eq!(decoder.chan_id(&trades("tBTCUSD")), None);
+        assert_eq!(decoder.stale_channel(Duration::from_secs(0)), None);
+
+        let data = r#"[2,[[401597395,1574694475039,0.005,7244.9]]]"#;
+        match decode_one(&mut decoder, data) {
+            WebSocketEvent::Unknown(frame) => assert_eq!(frame, data),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn untrackable_subscriptions_are_errors() {
+        let mut decoder = Decoder::default();
+        let text = r#"{"event":"subscribed","channel":"book","chanId":7,"symbol":"tBTCUSD","prec":"P9","freq":"F0","len":"25"}"#;
+
+        match decoder.decode(text) {
+            Err(Error::Protocol(msg)) => assert!(msg.contains("channel 7"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert_eq!(decoder.channel(7), None);
+    }
+
+    fn subscribed_session() -> (Session, Decoder) {
+        let mut session = Session::default();
+        let mut decoder = Decoder::default();
+
+        for symbol in &["tBTCUSD", "tETHUSD"] {
+            session.subscribed(trades(symbol));
+        }
+
+        decoder.decode(TRADES_SUBSCRIBED).unwrap();
+
+        (session, decoder)
+    }
+
+    fn assert_unsubscribe(text: &str, chan_id: u32) {
+        let msg: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(msg, json!({"event": "unsubscribe", "chanId": chan_id}));
+    }
+
+    #[test]
+    fn unsubscribing_sends_the_channel_id() {
+        let (mut session, decoder) = subscribed_session();
+
+        let text = session
+            .unsubscribed(Unsubscribe::Subscription(trades("tBTCUSD")), &decoder)
+            .unwrap();
+        assert_unsubscribe(&text, 2);
+
+        let (mut session, decoder) = subscribed_session();
+
+        let text = session
+            .unsubscribed(Unsubscribe::Channel(2), &decoder)
+            .unwrap();
+        assert_unsubscribe(&text, 2);
+    }
+
+    #[test]
+    fn unsubscribed_channels_are_not_restored() {
+        for target in [
+            Unsubscribe::Subscription(trades("tBTCUSD")),
+            Unsubscribe::Channel(2),
+        ] {
+            let (mut session, decoder) = subscribed_session();
+            session.unsubscribed(target, &decoder).unwrap();
+
+            assert_eq!(session.restore(None), vec![trades("tETHUSD").message()]);
+        }
+    }
+
+    #[test]
+    fn unsubscribing_an_unknown_subscription_fails() {
+        let (mut session, decoder) = subscribed_session();
+
+        // subscribed, but not confirmed yet
+        match session.unsubscribed(Unsubscribe::Subscription(trades("tETHUSD")), &decoder) {
+            Err(Error::Protocol(msg)) => assert!(msg.contains("tETHUSD"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    static ORDER: &str = r#"[1185815098,null,1575289350475,"tETHUSD",1575289351944,1575289351948,-3,-3,"LIMIT",null,null,null,0,"ACTIVE",null,null,0.3,0,0,0,null,null,null,0,0,null,null,null,"API>BFX",null,null,null]"#;
+    static POSITION: &str = r#"["tETHUST","ACTIVE",-0.2,167.01,0,0,null,null,null,null,null,142661142,null,null,null,0,null,0,null,{"reason":"TRADE","order_id":34271018124,"liq_stage":null,"trade_price":"168.83","trade_amount":"-0.2","order_id_oppo":34271020652}]"#;
+
+    fn account_event(text: &str) -> DataEvent {
+        match decode_one(&mut Decoder::default(), text) {
+            WebSocketEvent::Account(event, _) => event,
+            event => panic!("unexpected event {:?} for {}", event, text),
+        }
+    }
+
+    #[test]
+    fn account_events_are_decoded_by_type() {
+        let frames = [
+            (format!(r#"[0,"os",[{}]]"#, ORDER), "OrderSnapshotEvent"),
+            (format!(r#"[0,"on",{}]"#, ORDER), "OrderNewEvent"),
+            (format!(r#"[0,"ou",{}]"#, ORDER), "OrderUpdateEvent"),
+            (format!(r#"[0,"oc",{}]"#, ORDER), "OrderCancelEvent"),
+            (format!(r#"[0,"ps",[{}]]"#, POSITION), "PositionSnapshotEvent"),
+            (format!(r#"[0,"pu",{}]"#, POSITION), "PositionUpdateEvent"),
+            (
+                r#"[0,"ws",[["exchange","BTC",0.5,0,null],["margin","USD",100,0,100]]]"#.into(),
+                "WalletSnapshotEvent",
+            ),
+            (
+                r#"[0,"wu",["exchange","BTC",1.61169184,0,null,"Exchange 0.01 BTC for USD @ 7804.6",{"reason":"TRADE","order_id":34988418651}]]"#.into(),
+                "WalletUpdateEvent",
+            ),
+            (
+                r#"[0,"te",[402088407,"tETHUST",1574963975602,34938060782,-0.2,153.57,"MARKET",0,-1,null,null,0]]"#.into(),
+                "TradeExecutedEvent",
+            ),
+            (
+                r#"[0,"tu",[402088407,"tETHUST",1574963975602,34938060782,-0.2,153.57,"MARKET",0,-1,-0.061668,"USD"]]"#.into(),
+                "TradeExecutionUpdateEvent",
+            ),
+            (r#"[0,"bu",[4131.85,4131.85]]"#.into(), "BalanceUpdateEvent"),
+            (
+                format!(
+                    r#"[0,"n",[1575289447641,"on-req",null,null,{},null,"SUCCESS","Submitting limit sell order for -3 ETH."]]"#,
+                    ORDER
+                ),
+                "AccountNotificationEvent",
+            ),
+        ];
+
+        for (text, kind) in frames.iter() {
+            let event = account_event(text);
+            assert!(format!("{:?}", event).starts_with(kind), "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn account_events_keep_websocket_nulls() {
+        match account_event(&format!(r#"[0,"pu",{}]"#, POSITION)) {
+            DataEvent::PositionUpdateEvent(position) => {
+                assert_eq!(position.position_id(), 142661142);
+                assert_eq!(position.pl(), None);
+                assert_eq!(position.collateral_min(), None);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        match account_event(
+            r#"[0,"te",[402088407,"tETHUST",1574963975602,34938060782,-0.2,153.57,"MARKET",0,-1,null,null,0]]"#,
+        ) {
+            DataEvent::TradeExecutedEvent(trade) => {
+                assert_eq!(trade.fee(), None);
+                assert_eq!(trade.fee_currency(), None);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        match account_event(
+            r#"[0,"tu",[402088407,"tETHUST",1574963975602,34938060782,-0.2,153.57,"MARKET",0,-1,-0.061668,"USD"]]"#,
+        ) {
+            DataEvent::TradeExecutionUpdateEvent(trade) => {
+                assert_eq!(trade.fee(), Some(-0.061668));
+                assert_eq!(trade.fee_currency(), Some("USD"));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn account_heartbeats_yield_nothing() {
+        let mut decoder = Decoder::default();
+
+        assert!(decoder.decode(r#"[0,"hb"]"#).unwrap().is_empty());
+    }
+
+    // the notification of `kind` about the order `id`, of client id `cid`
+    fn notification(kind: &str, id: u64, cid: u64) -> WebSocketEvent {
+        let text = format!(
+            r#"[0,"n",[1575289447641,"{}",null,null,[{},null,{},"tBTCUSD",1575289447641,1575289447641,1,1,"LIMIT",null,null,null,0,"ACTIVE",null,null,9000,0,0,0,null,null,null,0,0,null,null,null,"API>BFX",null,null,null],null,"SUCCESS","ok"]]"#,
+            kind, id, cid
+        );
+
+        decode_one(&mut Decoder::default(), &text)
+    }
+
+    fn requests() -> OrderRequests {
+        let mut orders = OrderRequests::default();
+        orders.authenticating();
+        orders
+    }
+
+    // sends the message, returning the receiver of its reply
+    fn send(orders: &mut OrderRequests, message: OrderMessage) -> oneshot::Receiver<OrderReply> {
+        let (text, pending, rx) = message.into_parts();
+        assert_eq!(orders.send(text.clone(), pending, false), Some(text));
+        rx
+    }
+
+    fn submit(cid: u32) -> OrderMessage {
+        let order = OrderForm::new("tBTCUSD".into(), 9000., 1., OrderKind::Limit);
+        OrderMessage::submit(&order.with_cid(Some(cid))).unwrap()
+    }
+
+    fn resolved(rx: &mut oneshot::Receiver<OrderReply>) -> Option<OrderReply> {
+        rx.try_recv().ok()
+    }
+
+    #[test]
+    fn order_requests_are_matched_by_type_and_key() {
+        let mut orders = requests();
+        let mut submitted = send(&mut orders, submit(42));
+        let mut updated = send(
+            &mut orders,
+            OrderMessage::update(&UpdateOrderForm::new(7)).unwrap(),
+        );
+        let mut cancelled_by_id = send(
+            &mut orders,
+            OrderMessage::cancel(&CancelOrderForm::from_id(8)).unwrap(),
+        );
+        let mut cancelled_by_cid = send(
+            &mut orders,
+            OrderMessage::cancel(&CancelOrderForm::from_client(43, chrono::Utc::now())).unwrap(),
+        );
+
+        // another client id, another order id, or another type
+        for event in &[
+            notification("on-req", 1, 41),
+            notification("ou-req", 8, 42),
+            notification("oc-req", 7, 0),
+        ] {
+            orders.resolve(event);
+        }
+
+        assert!(resolved(&mut submitted).is_none());
+        assert!(resolved(&mut updated).is_none());
+        assert!(resolved(&mut cancelled_by_id).is_none());
+        assert!(resolved(&mut cancelled_by_cid).is_none());
+
+        orders.resolve(&notification("oc-req", 9, 43));
+        orders.resolve(&notification("oc-req", 8, 0));
+        orders.resolve(&notification("ou-req", 7, 0));
+        orders.resolve(&notification("on-req", 1, 42));
+
+        for (rx, id) in [
+            (&mut submitted, 1),
+            (&mut updated, 7),
+            (&mut cancelled_by_id, 8),
+            (&mut cancelled_by_cid, 9),
+        ] {
+            let notification = resolved(rx).unwrap().unwrap();
+            assert_eq!(notification.info()[0].as_u64(), Some(id));
+        }
+    }
+
+    #[test]
+    fn order_requests_without_key_are_answered_in_order() {
+        let mut orders = requests();
+        let mut first = send(
+            &mut orders,
+            OrderMessage::cancel_multi(&CancelOrdersForm::from_ids(vec![1, 2])).unwrap(),
+        );
+        let mut second = send(
+            &mut orders,
+            OrderMessage::cancel_multi(&CancelOrdersForm::from_ids(vec![1, 2])).unwrap(),
+        );
+
+        orders.resolve(&notification("on-req", 1, 42));
+        assert!(resolved(&mut first).is_none());
+
+        orders.resolve(&notification("oc_multi-req", 1, 0));
+        assert!(resolved(&mut first).unwrap().is_ok());
+        assert!(resolved(&mut second).is_none());
+    }
+
+    #[test]
+    fn order_requests_fail_without_authentication() {
+        let mut orders = OrderRequests::default();
+        let (text, pending, mut rx) = submit(42).into_parts();
+
+        assert_eq!(orders.send(text, pending, false), None);
+        assert!(matches!(resolved(&mut rx), Some(Err(Error::Protocol(_)))));
+    }
+
+    #[test]
+    fn order_requests_fail_with_the_authentication() {
+        let mut orders = requests();
+        let mut pending = send(&mut orders, submit(42));
+        let (text, held, mut held_rx) = submit(43).into_parts();
+        assert_eq!(orders.send(text, held, true), None);
+
+        let auth =
+            r#"{"event":"auth","status":"FAILED","chanId":0,"code":10100,"msg":"apikey: invalid"}"#;
+        orders.resolve(&decode_one(&mut Decoder::default(), auth));
+
+        for rx in [&mut pending, &mut held_rx] {
+            match resolved(rx) {
+                Some(Err(Error::Protocol(msg))) => assert!(msg.contains("apikey: invalid")),
+                other => panic!("unexpected reply {:?}", other),
+            }
+        }
+
+        let (text, pending, mut rx) = submit(44).into_parts();
+        assert_eq!(orders.send(text, pending, false), None);
+        assert!(matches!(resolved(&mut rx), Some(Err(Error::Protocol(_)))));
+    }
+
+    #[test]
+    fn error_events_fail_the_oldest_order_request() {
+        let mut orders = requests();
+        let mut first = send(&mut orders, submit(42));
+        let mut second = send(&mut orders, submit(43));
+
+        // about a channel, not an order
+        let subscription_error = r#"{"event":"error","msg":"subscribe: dup","code":10301,"channel":"trades","symbol":"tBTCUSD"}"#;
+        orders.resolve(&decode_one(&mut Decoder::default(), subscription_error));
+        assert!(resolved(&mut first).is_none());
+
+        let error = r#"{"event":"error","msg":"ERR_RATE_LIMIT","code":10305}"#;
+        orders.resolve(&decode_one(&mut Decoder::default(), error));
+
+        match resolved(&mut first) {
+            Some(Err(Error::Protocol(msg))) => assert!(msg.contains("ERR_RATE_LIMIT")),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(resolved(&mut second).is_none());
+    }
+
+    fn sequenced_decoder() -> Decoder {
+        let mut decoder = Decoder::default();
+        let flags = (ConfFlags::SEQ_ALL | ConfFlags::TIMESTAMP).bits();
+
+        decoder
+            .decode(&format!(
+                r#"{{"event":"conf","status":"OK","flags":{}}}"#,
+                flags
+            ))
+            .unwrap();
+        decoder.decode(TRADES_SUBSCRIBED).unwrap();
+        decoder
+    }
+
+    #[test]
+    fn sequence_numbers_are_stripped_from_the_payload() {
+        let mut decoder = sequenced_decoder();
+
+        match decode_one(
+            &mut decoder,
+            r#"[2,"te",[401597393,1574694478808,0.005,7245.3],1574694478900,1234]"#,
+        ) {
+            WebSocketEvent::Data(_, DataEvent::TradesTradingUpdateEvent(2, _, trade), info) => {
+                assert_eq!(trade.rate, 7245.3);
+                assert_eq!(
+                    info,
+                    FrameInfo {
+                        timestamp: Some(1574694478900),
+                        seq: Some(1234),
+                        auth_seq: None,
+                    }
+                );
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        match decode_one(
+            &mut decoder,
+            r#"[0,"bu",[4131.85,4131.85],1574694478901,1235,7]"#,
+        ) {
+            WebSocketEvent::Account(DataEvent::BalanceUpdateEvent(balance), info) => {
+                assert_eq!(balance.aum, 4131.85);
+                assert_eq!(
+                    info,
+                    FrameInfo {
+                        timestamp: Some(1574694478901),
+                        seq: Some(1235),
+                        auth_seq: Some(7),
+                    }
+                );
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // heartbeats carry the public sequence number only
+        assert!(decoder
+            .decode(r#"[0,"hb",1574694478902,1236]"#)
+            .unwrap()
+            .is_empty());
+        assert!(decoder
+            .decode(r#"[2,"hb",1574694478903,1237]"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn skipped_sequence_numbers_are_gaps() {
+        let mut decoder = sequenced_decoder();
+        decoder
+            .decode(r#"[0,"bu",[1,1],1574694478900,1,1]"#)
+            .unwrap();
+
+        let events = decoder
+            .decode(r#"[0,"bu",[1,1],1574694478901,3,2]"#)
+            .unwrap();
+        assert_eq!(events.len(), 2, "{:?}", events);
+        match events[0] {
+            WebSocketEvent::SequenceGap(gap) => assert_eq!(
+                gap,
+                SequenceGap {
+                    expected: 2,
+                    received: 3,
+                    account: false,
+                }
+            ),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+
+        let events = decoder
+            .decode(r#"[0,"bu",[1,1],1574694478902,4,5]"#)
+            .unwrap();
+        assert_eq!(events.len(), 2, "{:?}", events);
+        match events[0] {
+            WebSocketEvent::SequenceGap(gap) => assert_eq!(
+                gap,
+                SequenceGap {
+                    expected: 3,
+                    received: 5,
+                    account: true,
+                }
+            ),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+
+        // consecutive again
+        assert_eq!(
+            decoder.decode(r#"[2,"hb",1574694478903,5]"#).unwrap().len(),
+            0
+        );
+    }
+
+    #[test]
+    fn watchdog_is_enabled_by_default() {
+        let config = ClientConfig::default();
+
+        assert_eq!(config.heartbeat_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(Watchdog::tick(&config), Some(WATCHDOG_TICK));
+    }
+
+    #[test]
+    fn watchdog_fails_stale_channels() {
+        // every channel is stale at once
+        let config = ClientConfig::default().with_heartbeat_timeout(Duration::from_secs(0));
+        let mut watchdog = Watchdog::new(&config);
+        let mut decoder = Decoder::default();
+
+        assert_eq!(watchdog.check(&decoder).unwrap(), None);
+
+        decoder.decode(TRADES_SUBSCRIBED).unwrap();
+
+        match watchdog.check(&decoder) {
+            Err(Error::Disconnected(msg)) => assert!(msg.contains("channel 2"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let config = ClientConfig::default().with_heartbeat_timeout(Duration::from_secs(60));
+        assert_eq!(Watchdog::new(&config).check(&decoder).unwrap(), None);
+
+        // heartbeats keep the channel alive
+        decoder.decode(r#"[2,"hb"]"#).unwrap();
+        assert_eq!(decoder.stale_channel(Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn watchdog_fails_without_pong() {
+        // a ping is due on every check
+        let config = ClientConfig::default().with_ping_interval(Duration::from_secs(0));
+        let mut watchdog = Watchdog::new(&config);
+        let mut decoder = Decoder::default();
+
+        let ping: Value =
+            serde_json::from_str(&watchdog.check(&decoder).unwrap().unwrap()).unwrap();
+        assert_eq!(ping, json!({"event": "ping", "cid": 1}));
+
+        decoder
+            .decode(r#"{"event":"pong","ts":1574694478900,"cid":1}"#)
+            .unwrap();
+        let ping: Value =
+            serde_json::from_str(&watchdog.check(&decoder).unwrap().unwrap()).unwrap();
+        assert_eq!(ping["cid"], 2);
+
+        match watchdog.check(&decoder) {
+            Err(Error::Disconnected(msg)) => assert!(msg.contains("ping 2"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn info(code: u32) -> String {
+        format!(r#"{{"event":"info","code":{},"msg":"notice"}}"#, code)
+    }
+
+    #[test]
+    fn reconnect_requests_are_recorded() {
+        let mut decoder = Decoder::default();
+        assert!(!decoder.reconnect_requested());
+
+        match decode_one(&mut decoder, &info(20051)) {
+            WebSocketEvent::Info(NotificationEvent::Info(info)) => {
+                assert_eq!(info.info_code(), Some(InfoCode::ReconnectRequested))
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        assert!(decoder.reconnect_requested());
+        assert!(!decoder.maintenance());
+
+        // only until the next connection
+        decoder.reset();
+        assert!(!decoder.reconnect_requested());
+    }
+
+    #[test]
+    fn orders_are_held_during_maintenance() {
+        let mut decoder = Decoder::default();
+        let mut orders = requests();
+
+        decoder.decode(&info(20060)).unwrap();
+        assert!(decoder.maintenance());
+
+        let (text, pending, mut rx) = submit(42).into_parts();
+        assert_eq!(
+            orders.send(text.clone(), pending, decoder.maintenance()),
+            None
+        );
+        assert!(resolved(&mut rx).is_none());
+
+        decoder.decode(&info(20061)).unwrap();
+        assert!(!decoder.maintenance());
+        assert_eq!(orders.release(), vec![text]);
+        assert!(orders.release().is_empty());
+
+        // the released order awaits its notification
+        orders.resolve(&notification("on-req", 1, 42));
+        assert!(resolved(&mut rx).unwrap().is_ok());
+    }
+
+    #[test]
+    fn channels_are_subscribed_again_after_maintenance() {
+        let mut decoder = Decoder::default();
+        decoder.decode(TRADES_SUBSCRIBED).unwrap();
+        decoder.decode(&info(20060)).unwrap();
+        assert!(decoder.take_replies().is_empty());
+
+        decoder.decode(&info(20061)).unwrap();
+        let replies = decoder.take_replies();
+        assert_eq!(replies.len(), 1, "{:?}", replies);
+        assert_unsubscribe(&replies[0], 2);
+
+        decoder
+            .decode(r#"{"event":"unsubscribed","status":"OK","chanId":2}"#)
+            .unwrap();
+        assert_eq!(decoder.take_replies(), vec![trades("tBTCUSD").message()]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::errors::Error;
+
+/// How the client behaves when a request would exceed the Bitfinex rate limit of its endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RateLimitPolicy {
+    /// Requests are sent right away, the server enforces the limits
+    Disabled,
+    /// Waits until the request can be sent
+    #[default]
+    Wait,
+    /// Returns `Error::RateLimited` immediately
+    FailFast,
+}
+
+/// Bitfinex bans the IP for 60 seconds when a limit is exceeded.
+const BAN_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_sec: f64,
+    updated: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(per_minute: u32) -> Self {
+        let capacity = f64::from(per_minute.max(1));
+
+        Bucket {
+            tokens: capacity,
+            capacity,
+            refill_per_sec: capacity / 60.0,
+            updated: Instant::now(),
+            blocked_until: None,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long to wait until one is available.
+    fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.blocked_until = None;
+        }
+
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// Token buckets keyed by endpoint, shared by all the clones of a client.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    policy: RateLimitPolicy,
+    buckets: Mutex<HashMap<&'static str, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(policy: RateLimitPolicy) -> Self {
+        RateLimiter {
+            policy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) async fn acquire(&self, key: &'static str, per_minute: u32) -> Result<(), Error> {
+        if self.policy == RateLimitPolicy::Disabled {
+            return Ok(());
+        }
+
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let bucket = buckets
+                    .entry(key)
+                    .or_insert_with(|| Bucket::new(per_minute));
+
+                match bucket.try_acquire(Instant::now()) {
+                    Ok(()) => return Ok(()),
+                    Err(wait) => wait,
+                }
+            };
+
+            match self.policy {
+                RateLimitPolicy::FailFast => {
+                    return Err(Error::RateLimited {
+                        endpoint: key,
+                        retry_after: wait,
+                    })
+                }
+                _ => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Blocks the endpoint after the server reported that its limit was exceeded.
+    pub(crate) fn penalize(&self, key: &'static str, per_minute: u32) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::new(per_minute));
+
+        bucket.tokens = 0.0;
+        bucket.blocked_until = Some(Instant::now() + BAN_DURATION);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static KEY: &str = "test";
+
+    fn retry_after(result: Result<(), Error>) -> Duration {
+        match result {
+            Err(Error::RateLimited {
+                endpoint,
+                retry_after,
+            }) => {
+                assert_eq!(endpoint, KEY);
+                retry_after
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fail_fast_rejects_once_the_bucket_is_empty() {
+        let limiter = RateLimiter::new(RateLimitPolicy::FailFast);
+
+        for _ in 0..2 {
+            limiter.acquire(KEY, 2).await.unwrap();
+        }
+
+        assert_eq!(
+            retry_after(limiter.acquire(KEY, 2).await),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_over_time() {
+        let limiter = RateLimiter::new(RateLimitPolicy::FailFast);
+
+        for _ in 0..2 {
+            limiter.acquire(KEY, 2).await.unwrap();
+        }
+
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert_eq!(
+            retry_after(limiter.acquire(KEY, 2).await),
+            Duration::from_secs(15)
+        );
+
+        tokio::time::advance(Duration::from_secs(15)).await;
+        limiter.acquire(KEY, 2).await.unwrap();
+
+        // the bucket never holds more than a minute of requests
+        tokio::time::advance(Duration::from_secs(600)).await;
+        for _ in 0..2 {
+            limiter.acquire(KEY, 2).await.unwrap();
+        }
+        assert!(limiter.acquire(KEY, 2).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_sleeps_until_a_token_is_available() {
+        let limiter = RateLimiter::new(RateLimitPolicy::Wait);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.acquire(KEY, 2).await.unwrap();
+        }
+
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn penalize_blocks_for_a_minute() {
+        let limiter = RateLimiter::new(RateLimitPolicy::FailFast);
+        limiter.acquire(KEY, 90).await.unwrap();
+
+        limiter.penalize(KEY, 90);
+        assert_eq!(retry_after(limiter.acquire(KEY, 90).await), BAN_DURATION);
+
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert_eq!(
+            retry_after(limiter.acquire(KEY, 90).await),
+            Duration::from_secs(1)
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        limiter.acquire(KEY, 90).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disabled_never_limits() {
+        let limiter = RateLimiter::new(RateLimitPolicy::Disabled);
+
+        for _ in 0..10 {
+            limiter.acquire(KEY, 1).await.unwrap();
+        }
+    }
+}
@@ -105,8 +105,8 @@ impl<'de> Deserialize<'de> for WalletTransferResponse {
                 .ok_or(D::Error::custom("Empty central array"))?
                 .as_array()
             {
-                Some(array) => array.into_iter(),
-                None => middle_list.into_iter(),
+                Some(array) => array.iter(),
+                None => middle_list.iter(),
             }
         };
 
@@ -374,8 +374,8 @@ impl<'de> Deserialize<'de> for OrderResponse {
                 .ok_or(D::Error::custom("Empty central array"))?
                 .as_array()
             {
-                Some(array) => array.into_iter(),
-                None => middle_list.into_iter(),
+                Some(array) => array.iter(),
+                None => middle_list.iter(),
             }
         };
 
@@ -664,8 +664,8 @@ pub struct TradeResponse {
     order_type: Option<OrderKind>,
     order_price: Option<f64>,
     is_maker: bool,
-    fee: f64,
-    fee_currency: String,
+    fee: Option<f64>,
+    fee_currency: Option<String>,
 }
 
 impl TradeResponse {
@@ -696,11 +696,11 @@ impl TradeResponse {
     pub fn is_maker(&self) -> bool {
         self.is_maker
     }
-    pub fn fee(&self) -> f64 {
+    pub fn fee(&self) -> Option<f64> {
         self.fee
     }
-    pub fn fee_currency(&self) -> &str {
-        &self.fee_currency
+    pub fn fee_currency(&self) -> Option<&str> {
+        self.fee_currency.as_deref()
     }
 }
 
@@ -773,18 +773,16 @@ impl<'de> Deserialize<'de> for TradeResponse {
             .ok_or(D::Error::custom("Invalid hidden fee"))?
             > 0;
 
-        let fee = iterator
-            .next()
-            .ok_or(D::Error::custom("Missing fee"))?
-            .as_f64()
-            .ok_or(D::Error::custom("Invalid fee amount"))?;
+        // null in the `te` websocket events, the fee is only known by the `tu` ones
+        let fee = Option::deserialize(iterator.next().ok_or(D::Error::custom("Missing fee"))?)
+            .map_err(D::Error::custom)?;
 
-        let fee_currency = iterator
-            .next()
-            .ok_or(D::Error::custom("Missing fee currency"))?
-            .as_str()
-            .ok_or(D::Error::custom("Invalid fee currency"))?
-            .into();
+        let fee_currency = Option::deserialize(
+            iterator
+                .next()
+                .ok_or(D::Error::custom("Missing fee currency"))?,
+        )
+        .map_err(D::Error::custom)?;
 
         Ok(Self {
             trade_id,
@@ -802,6 +800,103 @@ impl<'de> Deserialize<'de> for TradeResponse {
     }
 }
 
+/// A notification pushed on the websocket account channel (`n`).
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    /// Millisecond Time Stamp of the notification
+    mts: u64,
+    /// Purpose of the notification ('on-req', 'oc-req', 'uca', 'fon-req', 'foc-req', ...)
+    #[serde(rename(serialize = "type"))]
+    notification_type: String,
+    /// unique ID of the message
+    message_id: Option<u64>,
+    /// The object the notification is about, e.g. the order of an 'on-req' notification
+    info: Value,
+    code: Option<i64>,
+    /// Status of the notification; it may vary over time (SUCCESS, ERROR, FAILURE, ...)
+    status: String,
+    /// Text of the notification
+    text: String,
+}
+
+impl Notification {
+    pub fn mts(&self) -> u64 {
+        self.mts
+    }
+    pub fn notification_type(&self) -> &str {
+        &self.notification_type
+    }
+    pub fn message_id(&self) -> Option<u64> {
+        self.message_id
+    }
+    pub fn info(&self) -> &Value {
+        &self.info
+    }
+    pub fn code(&self) -> Option<i64> {
+        self.code
+    }
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn is_success(&self) -> bool {
+        self.status == "SUCCESS"
+    }
+}
+
+impl<'de> Deserialize<'de> for Notification {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+
+        let mts = value
+            .get(0)
+            .ok_or(D::Error::custom("Missing mts"))?
+            .as_f64()
+            .ok_or(D::Error::custom("Invalid mts value"))?
+            .round() as u64;
+
+        let notification_type = String::deserialize(
+            value
+                .get(1)
+                .ok_or(D::Error::custom("Missing notification type"))?,
+        )
+        .map_err(D::Error::custom)?;
+
+        let message_id =
+            Option::deserialize(value.get(2).ok_or(D::Error::custom("Missing message_id"))?)
+                .map_err(D::Error::custom)?;
+
+        let info = value
+            .get(4)
+            .ok_or(D::Error::custom("Missing notification info"))?
+            .clone();
+
+        let code = Option::deserialize(value.get(5).ok_or(D::Error::custom("Missing code"))?)
+            .map_err(D::Error::custom)?;
+
+        let status = String::deserialize(value.get(6).ok_or(D::Error::custom("Missing status"))?)
+            .map_err(D::Error::custom)?;
+
+        let text = String::deserialize(value.get(7).ok_or(D::Error::custom("Missing text"))?)
+            .map_err(D::Error::custom)?;
+
+        Ok(Self {
+            mts,
+            notification_type,
+            message_id,
+            info,
+            code,
+            status,
+            text,
+        })
+    }
+}
+
 pub struct AccountFees {
     // Shows the maker fee rate for the account
     maker_fee: f64,
@@ -855,7 +950,7 @@ impl<'de> Deserialize<'de> for AccountFees {
             .ok_or(D::Error::custom("Invalid inner arrays"))?;
 
         let maker_array = taker_maker_arrays
-            .get(0)
+            .first()
             .ok_or(D::Error::custom("Missing maker fees array."))?
             .as_array()
             .ok_or(D::Error::custom("Invalid maker fees array"))?;
@@ -867,7 +962,7 @@ impl<'de> Deserialize<'de> for AccountFees {
             .ok_or(D::Error::custom("Invalid taker fees array"))?;
 
         let maker_fee = maker_array
-            .get(0)
+            .first()
             .ok_or(D::Error::custom("Missing maker fee."))?
             .as_f64()
             .ok_or(D::Error::custom("Invalid maker fee value."))?;
@@ -879,7 +974,7 @@ impl<'de> Deserialize<'de> for AccountFees {
             .ok_or(D::Error::custom("Invalid derivative rebate value."))?;
 
         let taker_to_crypto = taker_array
-            .get(0)
+            .first()
             .ok_or(D::Error::custom("Missing maker fee."))?
             .as_f64()
             .ok_or(D::Error::custom("Invalid maker fee value."))?;
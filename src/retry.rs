@@ -0,0 +1,258 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::errors::Error;
+
+/// Controls how failed REST requests are retried.
+///
+/// Public endpoints and authenticated read endpoints (`/v2/auth/r/...`) are retried
+/// automatically. Write endpoints (submitting or cancelling orders, transfers, ...) are only
+/// retried if explicitly enabled with [`RetryPolicy::with_retry_writes`], since a request that
+/// timed out may still have been executed by the server.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    statuses: Vec<u16>,
+    retry_timeouts: bool,
+    retry_connect_errors: bool,
+    retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            statuses: vec![500, 502, 503, 504],
+            retry_timeouts: true,
+            retry_connect_errors: true,
+            retry_writes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The delay before the first retry, doubled on every further retry up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Randomizes each delay between half and the full backoff.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// HTTP status codes that are considered transient.
+    pub fn with_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.statuses = statuses;
+        self
+    }
+
+    pub fn with_retry_timeouts(mut self, retry: bool) -> Self {
+        self.retry_timeouts = retry;
+        self
+    }
+
+    pub fn with_retry_connect_errors(mut self, retry: bool) -> Self {
+        self.retry_connect_errors = retry;
+        self
+    }
+
+    /// Also retries non-idempotent write endpoints. Use with care: an order may be placed twice.
+    pub fn with_retry_writes(mut self, retry: bool) -> Self {
+        self.retry_writes = retry;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub(crate) fn applies_to(&self, idempotent: bool) -> bool {
+        idempotent || self.retry_writes
+    }
+
+    pub(crate) fn should_retry(&self, error: &Error) -> bool {
+        match error {
+            Error::Api(e) => self.statuses.contains(&e.status()),
+            Error::Http(e) => {
+                (self.retry_timeouts && e.is_timeout())
+                    || (self.retry_connect_errors && e.is_connect())
+                    || e.status()
+                        .map(|s| self.statuses.contains(&s.as_u16()))
+                        .unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    /// The delay to wait after the given (1-based) failed attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        exponential_backoff(self.initial_backoff, self.max_backoff, self.jitter, attempt)
+    }
+}
+
+/// Controls how a dropped websocket connection is re-established.
+///
+/// After reconnecting, the connection is authenticated again and every subscription is renewed.
+/// The default policy retries forever, waiting from 1 to 30 seconds between attempts.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    max_attempts: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives up after `max_attempts` consecutive failed reconnections.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts.max(1));
+        self
+    }
+
+    /// The delay before the first reconnection, doubled after every failed one up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Randomizes each delay between half and the full backoff.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn max_attempts(&self) -> Option<u32> {
+        self.max_attempts
+    }
+
+    /// Followed when the server asks to reconnect and no policy is configured: the connection is
+    /// re-established once, giving up after 3 failed attempts.
+    pub(crate) fn requested() -> Self {
+        ReconnectPolicy::new().with_max_attempts(3)
+    }
+
+    pub(crate) fn allows(&self, attempt: u32) -> bool {
+        self.max_attempts.map(|max| attempt <= max).unwrap_or(true)
+    }
+
+    /// The delay to wait before the given (1-based) reconnection attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        exponential_backoff(self.initial_backoff, self.max_backoff, self.jitter, attempt)
+    }
+}
+
+fn exponential_backoff(initial: Duration, max: Duration, jitter: bool, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let backoff = initial.saturating_mul(1 << exponent).min(max);
+
+    if jitter {
+        let half = backoff / 2;
+        half + backoff.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    } else {
+        backoff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attempts_are_at_least_one() {
+        assert_eq!(RetryPolicy::new().max_attempts(), 3);
+        assert_eq!(RetryPolicy::none().max_attempts(), 1);
+        assert_eq!(RetryPolicy::new().with_max_attempts(0).max_attempts(), 1);
+
+        let policy = ReconnectPolicy::new().with_max_attempts(2);
+        assert!(policy.allows(2));
+        assert!(!policy.allows(3));
+        assert!(ReconnectPolicy::new().allows(u32::MAX));
+    }
+
+    #[test]
+    fn requested_reconnections_are_bounded() {
+        let policy = ReconnectPolicy::requested();
+        assert!(policy.allows(3));
+        assert!(!policy.allows(4));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::new()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .with_jitter(false);
+
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.backoff(attempt)).collect();
+
+        assert_eq!(
+            delays,
+            [100, 200, 400, 500, 500]
+                .iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn jitter_stays_between_half_and_full_backoff() {
+        let policy =
+            ReconnectPolicy::new().with_backoff(Duration::from_secs(1), Duration::from_secs(30));
+
+        for _ in 0..100 {
+            let delay = policy.backoff(3);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn only_writes_need_opting_in() {
+        let policy = RetryPolicy::new();
+
+        assert!(policy.applies_to(true));
+        assert!(!policy.applies_to(false));
+        assert!(policy.with_retry_writes(true).applies_to(false));
+    }
+}
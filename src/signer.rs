@@ -0,0 +1,327 @@
+use std::fmt;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::time::Duration;
+
+use hex::encode;
+use ring::hmac;
+#[cfg(unix)]
+use socket2::{Domain, SockAddr, Socket, Type};
+use zeroize::Zeroizing;
+
+use crate::errors::Error;
+
+/// Produces the `bfx-signature` of authenticated requests.
+///
+/// Implementing this trait allows the API secret to live outside of the process, e.g. in an
+/// external signing service.
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// The API key sent along with the signature.
+    fn api_key(&self) -> &str;
+
+    /// Signs `payload`, returning the hex encoded HMAC-SHA384 digest.
+    fn sign(&self, payload: &[u8]) -> Result<String, Error>;
+
+    /// Whether [`Signer::sign`] blocks, e.g. on I/O. The async clients then sign on a blocking
+    /// thread instead of stalling the runtime.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+}
+
+/// Signs `payload` from async code, moving blocking signers to the blocking thread pool.
+pub(crate) async fn sign_async(signer: &Arc<dyn Signer>, payload: String) -> Result<String, Error> {
+    if !signer.is_blocking() {
+        return signer.sign(payload.as_bytes());
+    }
+
+    let signer = Arc::clone(signer);
+    tokio::task::spawn_blocking(move || signer.sign(payload.as_bytes()))
+        .await
+        .map_err(|e| Error::Signing(Box::new(e)))?
+}
+
+/// Signs payloads in-process with HMAC-SHA384.
+///
+/// The HMAC key is derived once, and the secret it was derived from is zeroed right away. The
+/// derived key is kept in a single allocation until the signer is dropped; ring does not allow
+/// zeroing it.
+pub struct HmacSigner {
+    api_key: String,
+    key: Box<hmac::Key>,
+}
+
+impl HmacSigner {
+    pub fn new<K: Into<String>, S: Into<String>>(api_key: K, secret: S) -> Self {
+        let secret = Zeroizing::new(secret.into());
+
+        HmacSigner {
+            api_key: api_key.into(),
+            key: Box::new(hmac::Key::new(hmac::HMAC_SHA384, secret.as_bytes())),
+        }
+    }
+}
+
+impl fmt::Debug for HmacSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HmacSigner")
+            .field("api_key", &self.api_key)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Signer for HmacSigner {
+    fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    fn sign(&self, payload: &[u8]) -> Result<String, Error> {
+        let signature = encode(hmac::sign(&self.key, payload).as_ref());
+
+        Ok(signature)
+    }
+}
+
+/// Delegates signing to an external process listening on a Unix socket.
+///
+/// For every signature a new connection is opened and the hex encoded payload is written,
+/// followed by a newline. The process must answer with the hex encoded signature on a single
+/// line, within the timeout of the signer.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocketSigner {
+    api_key: String,
+    path: PathBuf,
+    timeout: Duration,
+}
+
+#[cfg(unix)]
+impl UnixSocketSigner {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new<S: Into<String>, P: AsRef<Path>>(api_key: S, path: P) -> Self {
+        UnixSocketSigner {
+            api_key: api_key.into(),
+            path: path.as_ref().to_owned(),
+            timeout: UnixSocketSigner::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets how long connecting, writing the payload and reading the signature may each take, 5
+    /// seconds by default. The async clients sign on a blocking thread, but still wait for the
+    /// signature, so this is usually at most the
+    /// [`crate::config::ClientConfig::request_timeout`].
+    ///
+    /// The connect timeout relies on the send timeout of the socket, which bounds connecting on
+    /// Linux; elsewhere connecting to a saturated socket fails right away.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(unix)]
+impl Signer for UnixSocketSigner {
+    fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    fn sign(&self, payload: &[u8]) -> Result<String, Error> {
+        let signing_error = |e: std::io::Error| Error::Signing(Box::new(e));
+
+        // the timeouts are set before connecting, so a saturated signing process cannot hang us
+        let socket = Socket::new(Domain::UNIX, Type::STREAM, None).map_err(signing_error)?;
+        socket
+            .set_read_timeout(Some(self.timeout))
+            .map_err(signing_error)?;
+        socket
+            .set_write_timeout(Some(self.timeout))
+            .map_err(signing_error)?;
+        socket
+            .connect(&SockAddr::unix(&self.path).map_err(signing_error)?)
+            .map_err(signing_error)?;
+
+        let mut stream = UnixStream::from(socket);
+        stream
+            .write_all(format!("{}\n", encode(payload)).as_bytes())
+            .map_err(signing_error)?;
+
+        let mut signature = String::new();
+        BufReader::new(stream)
+            .read_line(&mut signature)
+            .map_err(signing_error)?;
+
+        if !signature.ends_with('\n') {
+            return Err(Error::Signing(
+                "Signing process closed the connection before answering".into(),
+            ));
+        }
+
+        let signature = signature.trim();
+
+        if signature.is_empty() || !signature.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::Signing(
+                "Invalid signature from signing process".into(),
+            ));
+        }
+
+        Ok(signature.to_owned())
+    }
+
+    fn is_blocking(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    /// Serves a single signature request on a new socket, answering with `answer`.
+    fn signing_process<F>(name: &str, answer: F) -> (PathBuf, JoinHandle<()>)
+    where
+        F: FnOnce(&str) -> String + Send + 'static,
+    {
+        let path =
+            std::env::temp_dir().join(format!("bitfinex-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut payload = String::new();
+            reader.read_line(&mut payload).unwrap();
+
+            let answer = answer(payload.trim_end());
+            reader.get_mut().write_all(answer.as_bytes()).unwrap();
+        });
+
+        (path, server)
+    }
+
+    #[test]
+    fn unix_socket_signer_matches_hmac_signer() {
+        let (path, server) = signing_process("sign", |payload| {
+            let payload = hex::decode(payload).unwrap();
+            let signature = HmacSigner::new("key", "secret").sign(&payload).unwrap();
+            format!("{}\n", signature)
+        });
+
+        let payload = b"/api/v2/auth/r/wallets1600000000000{}";
+        let signature = UnixSocketSigner::new("key", &path).sign(payload).unwrap();
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            signature,
+            HmacSigner::new("key", "secret").sign(payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn unix_socket_signer_fails_without_newline() {
+        let (path, server) = signing_process("closed", |_| "abcdef".into());
+
+        let result = UnixSocketSigner::new("key", &path).sign(b"payload");
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::Signing(e)) => assert!(e.to_string().contains("closed the connection")),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unix_socket_signer_times_out() {
+        let path =
+            std::env::temp_dir().join(format!("bitfinex-timeout-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let result = UnixSocketSigner::new("key", &path)
+            .with_timeout(Duration::from_millis(100))
+            .sign(b"payload");
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::Signing(e)) => {
+                let kind = e.downcast_ref::<std::io::Error>().unwrap().kind();
+                assert!(matches!(
+                    kind,
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unix_socket_signer_times_out_connecting() {
+        let path = std::env::temp_dir().join(format!(
+            "bitfinex-connect-timeout-{}.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // a listener without backlog, saturated by a connection it never accepts
+        let listener = Socket::new(Domain::UNIX, Type::STREAM, None).unwrap();
+        listener.bind(&SockAddr::unix(&path).unwrap()).unwrap();
+        listener.listen(0).unwrap();
+        let _pending = UnixStream::connect(&path).unwrap();
+
+        let result = UnixSocketSigner::new("key", &path)
+            .with_timeout(Duration::from_millis(100))
+            .sign(b"payload");
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::Signing(e)) => {
+                let kind = e.downcast_ref::<std::io::Error>().unwrap().kind();
+                assert!(matches!(
+                    kind,
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn blocking_signer_signs_off_the_runtime() {
+        let (path, server) = signing_process("async", |payload| {
+            let payload = hex::decode(payload).unwrap();
+            let signature = HmacSigner::new("key", "secret").sign(&payload).unwrap();
+            format!("{}\n", signature)
+        });
+
+        let signer: Arc<dyn Signer> = Arc::new(UnixSocketSigner::new("key", &path));
+        let signature = sign_async(&signer, "AUTH1600000000000".into())
+            .await
+            .unwrap();
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(signer.is_blocking());
+        assert_eq!(
+            signature,
+            HmacSigner::new(String::from("key"), "secret")
+                .sign(b"AUTH1600000000000")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// The status of a derivatives pair, received on a `deriv:` status channel, e.g.
+/// `deriv:tBTCF0:USTF0`.
+#[derive(Clone, Debug)]
+pub struct DerivativeStatus {
+    pub mts: u64,
+    pub deriv_price: f64,
+    pub spot_price: f64,
+    pub insurance_fund_balance: Option<f64>,
+    pub next_funding_evt_mts: Option<u64>,
+    pub next_funding_accrued: Option<f64>,
+    pub next_funding_step: Option<u32>,
+    pub current_funding: Option<f64>,
+    pub mark_price: f64,
+    pub open_interest: Option<f64>,
+    pub clamp_min: Option<f64>,
+    pub clamp_max: Option<f64>,
+}
+
+/// A liquidated position, received on the `liq:global` status channel.
+#[derive(Clone, Debug)]
+pub struct Liquidation {
+    pub pos_id: u64,
+    pub mts: u64,
+    pub symbol: String,
+    pub amount: f64,
+    pub base_price: f64,
+    /// Whether the position was matched on the order book
+    pub is_match: bool,
+    /// Whether the position was sold at market price
+    pub is_market_sold: bool,
+    pub liquidation_price: Option<f64>,
+}
+
+// the payloads are padded with placeholders, and may gain fields at their end
+fn field<'de, T, E>(values: &'de [Value], index: usize, name: &str) -> Result<T, E>
+where
+    T: Deserialize<'de>,
+    E: Error,
+{
+    T::deserialize(values.get(index).unwrap_or(&Value::Null))
+        .map_err(|e| E::custom(format!("Invalid {}: {}", name, e)))
+}
+
+fn flag<E: Error>(values: &[Value], index: usize, name: &str) -> Result<bool, E> {
+    let flag: Option<u8> = field(values, index, name)?;
+
+    Ok(flag.unwrap_or(0) != 0)
+}
+
+impl<'de> Deserialize<'de> for DerivativeStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<Value>::deserialize(deserializer)?;
+
+        Ok(Self {
+            mts: field(&values, 0, "mts")?,
+            deriv_price: field(&values, 2, "deriv_price")?,
+            spot_price: field(&values, 3, "spot_price")?,
+            insurance_fund_balance: field(&values, 5, "insurance_fund_balance")?,
+            next_funding_evt_mts: field(&values, 7, "next_funding_evt_mts")?,
+            next_funding_accrued: field(&values, 8, "next_funding_accrued")?,
+            next_funding_step: field(&values, 9, "next_funding_step")?,
+            current_funding: field(&values, 11, "current_funding")?,
+            mark_price: field(&values, 14, "mark_price")?,
+            open_interest: field(&values, 17, "open_interest")?,
+            clamp_min: field(&values, 21, "clamp_min")?,
+            clamp_max: field(&values, 22, "clamp_max")?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Liquidation {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<Value>::deserialize(deserializer)?;
+
+        // the first element is always "pos"
+        Ok(Self {
+            pos_id: field(&values, 1, "pos_id")?,
+            mts: field(&values, 2, "mts")?,
+            symbol: field(&values, 4, "symbol")?,
+            amount: field(&values, 5, "amount")?,
+            base_price: field(&values, 6, "base_price")?,
+            is_match: flag(&values, 8, "is_match")?,
+            is_market_sold: flag(&values, 9, "is_market_sold")?,
+            liquidation_price: field(&values, 11, "liquidation_price")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derivative_status_skips_the_placeholders() {
+        // a `deriv:tBTCF0:USTF0` payload, with fields beyond the documented ones
+        let status: DerivativeStatus = serde_json::from_str(
+            r#"[1609144353000,null,26500.5,26495.2,null,1123456.7,null,1609156800000,0.00001234,240,null,0.0001,null,null,26501.1,null,null,1234.5,null,null,null,-0.005,0.005,null,42]"#,
+        )
+        .unwrap();
+
+        assert_eq!(status.mts, 1609144353000);
+        assert_eq!(status.deriv_price, 26500.5);
+        assert_eq!(status.spot_price, 26495.2);
+        assert_eq!(status.insurance_fund_balance, Some(1123456.7));
+        assert_eq!(status.next_funding_evt_mts, Some(1609156800000));
+        assert_eq!(status.next_funding_accrued, Some(0.00001234));
+        assert_eq!(status.next_funding_step, Some(240));
+        assert_eq!(status.current_funding, Some(0.0001));
+        assert_eq!(status.mark_price, 26501.1);
+        assert_eq!(status.open_interest, Some(1234.5));
+        assert_eq!(status.clamp_min, Some(-0.005));
+        assert_eq!(status.clamp_max, Some(0.005));
+    }
+
+    #[test]
+    fn derivative_status_may_lack_the_last_fields() {
+        let status: DerivativeStatus = serde_json::from_str(
+            r#"[1609144353000,null,26500.5,26495.2,null,null,null,null,null,null,null,null,null,null,26501.1]"#,
+        )
+        .unwrap();
+
+        assert_eq!(status.mark_price, 26501.1);
+        assert_eq!(status.insurance_fund_balance, None);
+        assert_eq!(status.open_interest, None);
+        assert_eq!(status.clamp_max, None);
+    }
+
+    #[test]
+    fn derivative_status_requires_its_prices() {
+        let error = serde_json::from_str::<DerivativeStatus>(
+            r#"[1609144353000,null,null,26495.2,null,null,null,null,null,null,null,null,null,null,26501.1]"#,
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("deriv_price"), "{}", error);
+    }
+
+    #[test]
+    fn liquidation_skips_the_placeholders() {
+        // an entry of a `liq:global` payload, with fields beyond the documented ones
+        let liquidations: Vec<Liquidation> = serde_json::from_str(
+            r#"[["pos",145400868,1609144352338,null,"tETHF0:USTF0",-1.67566173,736.96,null,1,0,null,730.12,null,7]]"#,
+        )
+        .unwrap();
+
+        let liquidation = &liquidations[0];
+        assert_eq!(liquidation.pos_id, 145400868);
+        assert_eq!(liquidation.mts, 1609144352338);
+        assert_eq!(liquidation.symbol, "tETHF0:USTF0");
+        assert_eq!(liquidation.amount, -1.67566173);
+        assert_eq!(liquidation.base_price, 736.96);
+        assert!(liquidation.is_match);
+        assert!(!liquidation.is_market_sold);
+        assert_eq!(liquidation.liquidation_price, Some(730.12));
+    }
+
+    #[test]
+    fn liquidation_flags_default_to_false() {
+        let liquidation: Liquidation = serde_json::from_str(
+            r#"["pos",145400868,1609144352338,null,"tETHF0:USTF0",-1.67566173,736.96,null,null,null,null,null]"#,
+        )
+        .unwrap();
+
+        assert!(!liquidation.is_match);
+        assert!(!liquidation.is_market_sold);
+        assert_eq!(liquidation.liquidation_price, None);
+    }
+}
@@ -1,8 +1,6 @@
-use serde_json::from_str;
-
 use crate::client::Client;
 use crate::endpoints::PublicEndpoint;
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TradingPairTicker {
@@ -45,7 +43,7 @@ impl Ticker {
         Ticker { client }
     }
 
-    pub async fn funding_currency<S>(&self, symbol: S) -> Result<FundingCurrency, BoxError>
+    pub async fn funding_currency<S>(&self, symbol: S) -> Result<FundingCurrency, Error>
     where
         S: Into<String>,
     {
@@ -54,10 +52,10 @@ impl Ticker {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
-    pub async fn trading_pair<S>(&self, symbol: S) -> Result<TradingPairTicker, BoxError>
+    pub async fn trading_pair<S>(&self, symbol: S) -> Result<TradingPairTicker, Error>
     where
         S: Into<String>,
     {
@@ -66,6 +64,6 @@ impl Ticker {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 }
@@ -1,8 +1,6 @@
-use serde_json::from_str;
-
 use crate::client::Client;
 use crate::endpoints::{AuthenticatedEndpoint, PublicEndpoint};
-use crate::errors::BoxError;
+use crate::errors::{from_json, Error};
 use crate::responses::TradeResponse;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,7 +30,7 @@ impl Trades {
         Trades { client }
     }
 
-    pub async fn funding_currency<S>(&self, symbol: S) -> Result<Vec<FundingCurrency>, BoxError>
+    pub async fn funding_currency<S>(&self, symbol: S) -> Result<Vec<FundingCurrency>, Error>
     where
         S: Into<String>,
     {
@@ -41,10 +39,10 @@ impl Trades {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
-    pub async fn trading_pair<S>(&self, symbol: S) -> Result<Vec<TradingPair>, BoxError>
+    pub async fn trading_pair<S>(&self, symbol: S) -> Result<Vec<TradingPair>, Error>
     where
         S: Into<String>,
     {
@@ -53,29 +51,29 @@ impl Trades {
         };
         let data = self.client.get(endpoint).await?;
 
-        Ok(from_str(data.as_str())?)
+        from_json(data.as_str())
     }
 
-    pub async fn history<S: ToString>(&self, symbol: S) -> Result<Vec<TradeResponse>, BoxError> {
+    pub async fn history<S: ToString>(&self, symbol: S) -> Result<Vec<TradeResponse>, Error> {
         let endpoint = AuthenticatedEndpoint::OrdersHistory {
             symbol: Some(symbol.to_string()),
         };
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 
     pub async fn generated_by_order<S: ToString>(
         &self,
         symbol: S,
         order_id: u64,
-    ) -> Result<Vec<TradeResponse>, BoxError> {
+    ) -> Result<Vec<TradeResponse>, Error> {
         let endpoint = AuthenticatedEndpoint::OrderTrades {
             symbol: symbol.to_string(),
             order_id,
         };
         let data = self.client.post_signed(&endpoint, "{}".into()).await?;
 
-        Ok(from_str(&data)?)
+        from_json(&data)
     }
 }
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+use crate::config::ClientConfig;
+use crate::errors::Error;
+
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HttpResponse, Error>> + Send + 'a>>;
+
+/// A fully built REST request, ready to be sent.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    /// Absolute URL, including the query string
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+/// Sends the REST requests of a client.
+///
+/// The default implementation is [`ReqwestTransport`]. [`MockTransport`] serves canned responses
+/// and allows the library to be exercised without network access.
+pub trait HttpTransport: Debug + Send + Sync {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+/// Sends requests over HTTPS with `reqwest`.
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: reqwest::Client) -> Self {
+        ReqwestTransport { client }
+    }
+
+    /// Builds a client applying the timeout, proxy and TLS settings of `config`.
+    pub fn from_config(config: &ClientConfig) -> Result<Self, Error> {
+        let mut builder =
+            reqwest::Client::builder().danger_accept_invalid_certs(config.accept_invalid_certs());
+
+        if let Some(timeout) = config.connect_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = config.request_timeout() {
+            builder = builder.timeout(timeout);
+        }
+
+        if let Some(proxy) = config.proxy() {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        for pem in config.root_certificates() {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+        }
+
+        Ok(ReqwestTransport::with_client(builder.build()?))
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, &request.url)
+                .headers(request.headers);
+
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+
+            let response = builder.send().await?;
+            let status = response.status();
+            let body = response.text().await?;
+
+            Ok(HttpResponse { status, body })
+        })
+    }
+}
+
+// the path of a mocked request and its payload, if it must match
+type MockKey = (String, Option<String>);
+
+/// Serves canned responses, keyed by request path and payload.
+///
+/// Paths are relative to the host and may include the query string, e.g. `/v2/auth/r/wallets`
+/// or `/v2/tickers?symbols=tBTCUSD`. A response registered without a payload matches any
+/// payload. Several responses registered for the same request are served in order, the last one
+/// being repeated. Requests without a matching response fail with a 404 status.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use bitfinex::api::Bitfinex;
+/// use bitfinex::config::ClientConfig;
+/// use bitfinex::transport::MockTransport;
+///
+/// let transport = Arc::new(MockTransport::new().with_response(
+///     "/v2/auth/r/wallets",
+///     None,
+///     r#"[["exchange","USD",10.5,0,null]]"#,
+/// ));
+/// let config = ClientConfig::new().with_transport(transport.clone());
+/// let api = Bitfinex::with_config(None, None, config).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<MockKey, VecDeque<HttpResponse>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests to `path` (and `payload`, if given) with a 200 status and `body`.
+    pub fn with_response<P, B>(self, path: P, payload: Option<&str>, body: B) -> Self
+    where
+        P: Into<String>,
+        B: Into<String>,
+    {
+        self.with_status_response(path, payload, StatusCode::OK, body)
+    }
+
+    /// Answers requests to `path` (and `payload`, if given) with `status` and `body`.
+    pub fn with_status_response<P, B>(
+        mut self,
+        path: P,
+        payload: Option<&str>,
+        status: StatusCode,
+        body: B,
+    ) -> Self
+    where
+        P: Into<String>,
+        B: Into<String>,
+    {
+        self.responses
+            .get_mut()
+            .unwrap()
+            .entry((path.into(), payload.map(String::from)))
+            .or_default()
+            .push_back(HttpResponse {
+                status,
+                body: body.into(),
+            });
+        self
+    }
+
+    /// Every request received so far, in order.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn find(&self, request: &HttpRequest) -> Option<HttpResponse> {
+        let url = Url::parse(&request.url).ok()?;
+        let path = url.path().to_owned();
+        let full_path = match url.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.clone(),
+        };
+
+        let mut responses = self.responses.lock().unwrap();
+        let key = [full_path, path]
+            .iter()
+            .flat_map(|p| vec![(p.clone(), request.body.clone()), (p.clone(), None)])
+            .find(|key| responses.contains_key(key))?;
+        let queue = responses.get_mut(&key)?;
+
+        if queue.len() > 1 {
+            queue.pop_front()
+        } else {
+            queue.front().cloned()
+        }
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        let response = self.find(&request).unwrap_or(HttpResponse {
+            status: StatusCode::NOT_FOUND,
+            body: format!("[\"error\",10020,\"no mock response for {}\"]", request.url),
+        });
+
+        self.requests.lock().unwrap().push(request);
+
+        Box::pin(async move { Ok(response) })
+    }
+}
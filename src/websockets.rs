@@ -1,29 +1,126 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
 use std::sync::mpsc::{self, channel};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use tokio::sync::oneshot;
 
-use serde_json::from_str;
 use tungstenite::client::AutoStream;
-use tungstenite::connect;
 use tungstenite::handshake::client::Response;
 use tungstenite::protocol::WebSocket;
 use tungstenite::Message;
-use url::Url;
 
-use crate::auth;
-use crate::errors::BoxError;
-use crate::events::{DataEvent, NotificationEvent};
+use crate::book::{BookFrequency, BookLength, BookPrecision};
+use crate::candles::CandleKey;
+use crate::cassette::{Cassette, CassetteRecorder};
+use crate::config::ClientConfig;
+use crate::connector::connect_websocket;
+use crate::errors::Error;
+use crate::events::{
+    DataEvent, FrameInfo, NotificationEvent, SequenceGap, SubscriptionErrorMessage, WebSocketEvent,
+};
+use crate::orders::{
+    CancelOrderForm, CancelOrdersForm, OrderForm, OrderOperation, UpdateOrderForm,
+};
+use crate::protocol::{
+    conf_message, is_connection_error, Auth, Decoder, OrderMessage, OrderReply, OrderRequests,
+    PendingOrder, Session, Unsubscribe, Watchdog,
+};
+use crate::responses::Notification;
+use crate::retry::ReconnectPolicy;
+use crate::signer::{HmacSigner, Signer};
 
-static INFO: &'static str = "info";
-static SUBSCRIBED: &'static str = "subscribed";
-static AUTH: &'static str = "auth";
-static WEBSOCKET_URL: &'static str = "wss://api.bitfinex.com/ws/2";
-static DEAD_MAN_SWITCH_FLAG: u8 = 4;
+pub(crate) static WEBSOCKET_URL: &str = "wss://api.bitfinex.com/ws/2";
 
 pub trait EventHandler {
     fn on_connect(&mut self, event: NotificationEvent);
     fn on_auth(&mut self, event: NotificationEvent);
     fn on_subscribed(&mut self, event: NotificationEvent);
     fn on_data_event(&mut self, event: DataEvent);
-    fn on_error(&mut self, message: BoxError);
+    fn on_error(&mut self, message: Error);
+
+    /// Called with the data of a channel, instead of `on_data_event`. Forwards to
+    /// `on_data_event` by default.
+    fn on_channel_event(&mut self, _channel: &Channel, event: DataEvent, _info: &FrameInfo) {
+        self.on_data_event(event);
+    }
+
+    /// Called when the options of [`WebSockets::configure`] were applied, or rejected.
+    fn on_configured(&mut self, _event: NotificationEvent) {}
+
+    /// Called when a channel was unsubscribed.
+    fn on_unsubscribed(&mut self, _event: NotificationEvent) {}
+
+    /// Called when a subscribe or unsubscribe request was rejected. Forwards to `on_error` by
+    /// default.
+    fn on_subscription_error(&mut self, event: NotificationEvent) {
+        let error = match event {
+            NotificationEvent::SubscriptionError(e) => format!("{} ({})", e.msg, e.code),
+            event => format!("{:?}", event),
+        };
+
+        self.on_error(Error::Protocol(error));
+    }
+
+    /// Called when the checksum of a book does not match its local copy, before subscribing to
+    /// the book again. Forwards to `on_book_reset` by default.
+    fn on_checksum_mismatch(&mut self, channel: &Channel) {
+        self.on_book_reset(&channel.subscription);
+    }
+
+    /// Called with the updates of the authenticated account. Forwards to `on_data_event` by
+    /// default.
+    fn on_account_event(&mut self, event: DataEvent, _info: &FrameInfo) {
+        self.on_data_event(event);
+    }
+
+    /// Called when a sequence number was skipped, see [`ConfFlags::SEQ_ALL`]. Forwards to
+    /// `on_error` by default.
+    fn on_sequence_gap(&mut self, gap: &SequenceGap) {
+        self.on_error(Error::Protocol(format!(
+            "Sequence gap: expected {}, received {}",
+            gap.expected, gap.received
+        )));
+    }
+
+    /// Called with the answers to the pings, see [`ClientConfig::with_ping_interval`].
+    fn on_pong(&mut self, _event: NotificationEvent) {}
+
+    /// Called with the frames which are not modelled, instead of failing the event loop.
+    fn on_unknown(&mut self, _frame: &str) {}
+
+    /// Called when the connection drops and a reconnect policy is configured, before
+    /// reconnecting.
+    fn on_disconnect(&mut self, _error: &Error) {}
+
+    /// Called once reconnected, after the authentication and every subscription were sent again.
+    fn on_reconnect(&mut self) {}
+
+    /// Called for every book subscription when the connection drops. The local book is stale and
+    /// must be cleared, a new snapshot is received once resubscribed.
+    fn on_book_reset(&mut self, _subscription: &Subscription) {}
+}
+
+bitflags! {
+    /// The options of a connection, see [`WebSockets::configure`].
+    #[derive(Default)]
+    pub struct ConfFlags: u32 {
+        /// Sends the checksum of the books along with their updates. The books subscribed with
+        /// `subscribe_books` and `subscribe_raw_books` are verified, and subscribed again when
+        /// their checksum does not match.
+        const OB_CHECKSUM = 131072;
+        /// Appends the server timestamp to every frame, see [`FrameInfo::timestamp`].
+        const TIMESTAMP = 32768;
+        /// Appends sequence numbers to every frame, see [`FrameInfo::seq`]. A skipped number is
+        /// reported as a [`SequenceGap`].
+        const SEQ_ALL = 65536;
+        /// Sends the updates of the books in bulk, as
+        /// [`DataEvent::BookTradingBulkUpdateEvent`] and the like.
+        const BULK_UPDATES = 536870912;
+    }
 }
 
 pub enum EventType {
@@ -31,10 +128,263 @@ pub enum EventType {
     Trading,
 }
 
+impl EventType {
+    pub(crate) fn format_symbol(&self, symbol: String) -> String {
+        match self {
+            EventType::Funding => format!("f{}", symbol),
+            EventType::Trading => format!("t{}", symbol),
+        }
+    }
+}
+
+/// A channel subscription, as sent by the `subscribe_*` methods.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subscription {
+    Ticker {
+        symbol: String,
+    },
+    Trades {
+        symbol: String,
+    },
+    Candles {
+        key: String,
+    },
+    Books {
+        symbol: String,
+        prec: BookPrecision,
+        freq: BookFrequency,
+        len: BookLength,
+    },
+    RawBooks {
+        symbol: String,
+    },
+    /// A status channel, e.g. `deriv:tBTCF0:USTF0` or `liq:global`
+    Status {
+        key: String,
+    },
+}
+
+impl Subscription {
+    pub fn kind(&self) -> ChannelKind {
+        match self {
+            Subscription::Ticker { .. } => ChannelKind::Ticker,
+            Subscription::Trades { .. } => ChannelKind::Trades,
+            Subscription::Candles { .. } => ChannelKind::Candles,
+            Subscription::Books { .. } => ChannelKind::Book,
+            Subscription::RawBooks { .. } => ChannelKind::RawBook,
+            Subscription::Status { .. } => ChannelKind::Status,
+        }
+    }
+
+    /// The trading pair or funding currency, e.g. `tBTCUSD` or `fUSD`.
+    pub fn symbol(&self) -> &str {
+        match self {
+            Subscription::Ticker { symbol }
+            | Subscription::Trades { symbol }
+            | Subscription::Books { symbol, .. }
+            | Subscription::RawBooks { symbol } => symbol,
+            // trade:1m:tBTCUSD, trade:1m:tBTCF0:USTF0 or trade:1m:fUSD:p30
+            Subscription::Candles { key } => {
+                let symbol = key.splitn(3, ':').nth(2).unwrap_or(key);
+
+                if symbol.starts_with('f') {
+                    symbol.split(':').next().unwrap_or(symbol)
+                } else {
+                    symbol
+                }
+            }
+            // deriv:tBTCF0:USTF0 or liq:global
+            Subscription::Status { key } => key.split(':').nth(1).unwrap_or(key),
+        }
+    }
+
+    /// The book precision, `R0` for raw books.
+    pub fn precision(&self) -> Option<BookPrecision> {
+        match self {
+            Subscription::Books { prec, .. } => Some(*prec),
+            Subscription::RawBooks { .. } => Some(BookPrecision::R0),
+            _ => None,
+        }
+    }
+
+    /// Whether the subscription is to an order book, raw or not.
+    pub fn is_book(&self) -> bool {
+        matches!(
+            self,
+            Subscription::Books { .. } | Subscription::RawBooks { .. }
+        )
+    }
+
+    /// Whether `error` rejected this subscription, as told by the fields it echoes back. Books
+    /// of the same symbol are not told apart.
+    pub(crate) fn is_rejected_by(&self, error: &SubscriptionErrorMessage) -> bool {
+        let (channel, target, echoed) = match self {
+            Subscription::Ticker { symbol } => ("ticker", symbol, &error.symbol),
+            Subscription::Trades { symbol } => ("trades", symbol, &error.symbol),
+            Subscription::Books { symbol, .. } | Subscription::RawBooks { symbol } => {
+                ("book", symbol, &error.symbol)
+            }
+            Subscription::Candles { key } => ("candles", key, &error.key),
+            Subscription::Status { key } => ("status", key, &error.key),
+        };
+
+        // unsubscriptions are rejected with the id of their channel
+        error.chan_id.is_none()
+            && error.channel.as_deref() == Some(channel)
+            && echoed.as_deref() == Some(target.as_str())
+    }
+
+    pub(crate) fn is_liquidations(&self) -> bool {
+        matches!(self, Subscription::Status { key } if key.starts_with("liq:"))
+    }
+
+    pub(crate) fn is_funding(&self) -> bool {
+        self.symbol().starts_with('f')
+    }
+
+    /// A book subscription, raw books must be subscribed with `subscribe_raw_books`.
+    pub(crate) fn books(
+        symbol: String,
+        prec: BookPrecision,
+        freq: BookFrequency,
+        len: BookLength,
+    ) -> Result<Subscription, Error> {
+        if prec == BookPrecision::R0 {
+            return Err(Error::InvalidInput(
+                "Raw books are subscribed with subscribe_raw_books".into(),
+            ));
+        }
+
+        Ok(Subscription::Books {
+            symbol,
+            prec,
+            freq,
+            len,
+        })
+    }
+
+    pub(crate) fn message(&self) -> String {
+        let msg = match self {
+            Subscription::Ticker { symbol } => {
+                json!({"event": "subscribe", "channel": "ticker", "symbol": symbol })
+            }
+            Subscription::Trades { symbol } => {
+                json!({"event": "subscribe", "channel": "trades", "symbol": symbol })
+            }
+            Subscription::Candles { key } => {
+                json!({"event": "subscribe", "channel": "candles", "key": key })
+            }
+            Subscription::Books {
+                symbol,
+                prec,
+                freq,
+                len,
+            } => json!(
+            {
+                "event": "subscribe",
+                "channel": "book",
+                "symbol": symbol,
+                "prec": prec,
+                "freq": freq,
+                "len": len.levels()
+            }),
+            Subscription::RawBooks { symbol } => json!(
+            {
+                "event": "subscribe",
+                "channel": "book",
+                "prec": "R0",
+                "symbol": symbol
+            }),
+            Subscription::Status { key } => {
+                json!({"event": "subscribe", "channel": "status", "key": key })
+            }
+        };
+
+        msg.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelKind {
+    Ticker,
+    Trades,
+    Book,
+    RawBook,
+    Candles,
+    Status,
+}
+
+/// A channel confirmed by a `subscribed` event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub chan_id: u32,
+    pub subscription: Subscription,
+}
+
+impl Channel {
+    pub fn kind(&self) -> ChannelKind {
+        self.subscription.kind()
+    }
+
+    pub fn symbol(&self) -> &str {
+        self.subscription.symbol()
+    }
+
+    pub fn precision(&self) -> Option<BookPrecision> {
+        self.subscription.precision()
+    }
+}
+
+/// Resolves with the notification answering an order request, once received on the connection.
+///
+/// Whether the request succeeded is told by [`Notification::is_success`]. Fails with
+/// [`Error::Disconnected`] if the connection drops before the notification is received, and
+/// with [`Error::Protocol`] if the connection is not authenticated or the server answered with
+/// an `error` event.
+#[derive(Debug)]
+pub struct OrderRequest {
+    rx: oneshot::Receiver<OrderReply>,
+}
+
+impl OrderRequest {
+    pub(crate) fn new(rx: oneshot::Receiver<OrderReply>) -> Self {
+        OrderRequest { rx }
+    }
+
+    /// Blocks until the notification is received, while the event loop runs on another thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called within a tokio runtime, even from a synchronous function it runs: the
+    /// current thread would be blocked. Await the request instead.
+    pub fn wait(self) -> Result<Notification, Error> {
+        self.rx.blocking_recv().map_err(request_dropped)?
+    }
+}
+
+impl Future for OrderRequest {
+    type Output = Result<Notification, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|reply| reply.map_err(request_dropped)?)
+    }
+}
+
+fn request_dropped(_: oneshot::error::RecvError) -> Error {
+    Error::Disconnected("Connection closed before the order notification".into())
+}
+
 #[derive(Debug)]
 enum WsMessage {
     Close,
     Text(String),
+    Subscribe(Subscription),
+    Unsubscribe(Unsubscribe),
+    Auth(Auth, String),
+    Configure(ConfFlags),
+    Order(String, PendingOrder),
 }
 
 pub struct WebSockets {
@@ -42,10 +392,26 @@ pub struct WebSockets {
     sender: Sender,
     rx: mpsc::Receiver<WsMessage>,
     event_handler: Option<Box<dyn EventHandler>>,
+    recorder: Option<CassetteRecorder>,
+    session: Session,
+    decoder: Decoder,
+    orders: OrderRequests,
+    watchdog: Watchdog,
+    config: ClientConfig,
+}
+
+impl Default for WebSockets {
+    fn default() -> Self {
+        WebSockets::new()
+    }
 }
 
 impl WebSockets {
     pub fn new() -> WebSockets {
+        WebSockets::with_config(ClientConfig::default())
+    }
+
+    pub fn with_config(config: ClientConfig) -> WebSockets {
         let (tx, rx) = channel::<WsMessage>();
         let sender = Sender { tx };
 
@@ -54,22 +420,21 @@ impl WebSockets {
             sender,
             rx,
             event_handler: None,
+            recorder: None,
+            session: Session::default(),
+            decoder: Decoder::default(),
+            orders: OrderRequests::default(),
+            watchdog: Watchdog::new(&config),
+            config,
         }
     }
 
-    pub fn connect(&mut self) -> Result<(), BoxError> {
-        let wss: String = format!("{}", WEBSOCKET_URL);
-        let url = Url::parse(&wss)?;
+    /// Opens the connection, applying the timeout, proxy and TLS settings of the configuration.
+    pub fn connect(&mut self) -> Result<(), Error> {
+        self.socket = Some(connect_websocket(&self.config)?);
+        self.watchdog.reset();
 
-        match connect(url) {
-            Ok(answer) => {
-                self.socket = Some(answer);
-                Ok(())
-            }
-            Err(e) => {
-                bail!(format!("Error during handshake {}", e))
-            }
-        }
+        Ok(())
     }
 
     pub fn add_event_handler<H>(&mut self, handler: H)
@@ -79,203 +444,630 @@ impl WebSockets {
         self.event_handler = Some(Box::new(handler));
     }
 
+    /// Whether the platform is in maintenance, see [`crate::events::InfoCode`]. Orders are held
+    /// until it ends.
+    pub fn in_maintenance(&self) -> bool {
+        self.decoder.maintenance()
+    }
+
+    /// The channel with the given id, once its subscription was confirmed.
+    pub fn channel(&self, chan_id: u32) -> Option<&Channel> {
+        self.decoder.channel(chan_id)
+    }
+
+    /// Records every text frame received by `event_loop` to a cassette.
+    pub fn record_to(&mut self, recorder: CassetteRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Feeds the frames of a recorded cassette to the event handler, without any network access.
+    /// Frames which cannot be decoded are reported to `on_error`.
+    pub fn replay(&mut self, cassette: &Cassette) -> Result<(), Error> {
+        for frame in cassette.frames() {
+            WebSockets::dispatch(
+                &mut self.event_handler,
+                &mut self.decoder,
+                &mut self.orders,
+                frame,
+            );
+
+            // there is no connection to reply on
+            self.decoder.take_replies();
+        }
+
+        Ok(())
+    }
+
     /// Authenticates the connection.
     ///
-    /// The connection will be authenticated until it is disconnected.
+    /// The connection will be authenticated until it is disconnected. With a reconnect policy, it
+    /// is authenticated again after every reconnection.
     ///
     /// # Arguments
     ///
     /// * `api_key` - The API key
     /// * `api_secret` - The API secret
     /// * `dms` - Whether the dead man switch is enabled. If true, all account orders will be
-    ///           cancelled when the socket is closed.
+    ///   cancelled when the socket is closed.
     pub async fn auth<S>(
         &mut self,
         api_key: S,
         api_secret: S,
         dms: bool,
         filters: &[&str],
-    ) -> Result<(), BoxError>
+    ) -> Result<(), Error>
     where
         S: AsRef<str>,
     {
-        let nonce = auth::generate_nonce().await?;
-        let auth_payload = format!("AUTH{}", nonce);
-        let signature =
-            auth::sign_payload(api_secret.as_ref().as_bytes(), auth_payload.as_bytes())?;
-
-        let msg = json!({
-            "event": "auth",
-            "apiKey": api_key.as_ref(),
-            "authSig": signature,
-            "authNonce": nonce,
-            "authPayload": auth_payload,
-            "dms": if dms {Some(DEAD_MAN_SWITCH_FLAG)} else {None},
-            "filters": filters,
-        });
+        let signer = HmacSigner::new(api_key.as_ref(), api_secret.as_ref());
+
+        self.auth_with_signer(Arc::new(signer), dms, filters).await
+    }
+
+    /// Authenticates the connection, delegating the signature to `signer`.
+    ///
+    /// See [`WebSockets::auth`].
+    pub async fn auth_with_signer(
+        &mut self,
+        signer: Arc<dyn Signer>,
+        dms: bool,
+        filters: &[&str],
+    ) -> Result<(), Error> {
+        let auth = Auth::new(signer, dms, filters);
+        let msg = auth
+            .message_async(self.config.nonce_provider().as_ref())
+            .await?;
 
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
+        if let Err(error_msg) = self.sender.tx.send(WsMessage::Auth(auth, msg)) {
+            self.error_handler(Error::Disconnected(error_msg.to_string()));
         }
 
         Ok(())
     }
 
+    /// Enables the options of `flags` on the connection, and disables the others. The options are
+    /// enabled again after every reconnection.
+    pub fn configure(&mut self, flags: ConfFlags) {
+        if let Err(error_msg) = self.sender.tx.send(WsMessage::Configure(flags)) {
+            self.error_handler(Error::Disconnected(error_msg.to_string()));
+        }
+    }
+
     pub fn subscribe_ticker<S>(&mut self, symbol: S, et: EventType)
     where
         S: Into<String>,
     {
-        let local_symbol = self.format_symbol(symbol.into(), et);
-        let msg = json!({"event": "subscribe", "channel": "ticker", "symbol": local_symbol });
+        let symbol = et.format_symbol(symbol.into());
 
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
-        }
+        self.subscribe(Subscription::Ticker { symbol });
     }
 
     pub fn subscribe_trades<S>(&mut self, symbol: S, et: EventType)
     where
         S: Into<String>,
     {
-        let local_symbol = self.format_symbol(symbol.into(), et);
-        let msg = json!({"event": "subscribe", "channel": "trades", "symbol": local_symbol });
+        let symbol = et.format_symbol(symbol.into());
 
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
-        }
+        self.subscribe(Subscription::Trades { symbol });
     }
 
-    pub fn subscribe_candles<S>(&mut self, symbol: S, timeframe: S)
+    /// Subscribes to the candles of `key`, e.g. [`CandleKey::trading`] or
+    /// [`CandleKey::funding`].
+    pub fn subscribe_candles(&mut self, key: CandleKey) {
+        self.subscribe(Subscription::Candles {
+            key: key.to_string(),
+        });
+    }
+
+    /// Subscribes to a status channel, e.g. `deriv:tBTCF0:USTF0` for the status of a
+    /// derivatives pair or `liq:global` for the liquidations.
+    pub fn subscribe_status<S>(&mut self, key: S)
     where
         S: Into<String>,
     {
-        let key: String = format!("trade:{}:t{}", timeframe.into(), symbol.into());
-        let msg = json!({"event": "subscribe", "channel": "candles", "key": key });
-
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
-        }
+        self.subscribe(Subscription::Status { key: key.into() });
     }
 
-    pub fn subscribe_books<S, P, F>(&mut self, symbol: S, et: EventType, prec: P, freq: F, len: u32)
+    /// Subscribes to a book, failing with [`Error::InvalidInput`] on an invalid combination of
+    /// parameters, e.g. the `R0` precision of raw books.
+    pub fn subscribe_books<S>(
+        &mut self,
+        symbol: S,
+        et: EventType,
+        prec: BookPrecision,
+        freq: BookFrequency,
+        len: BookLength,
+    ) -> Result<(), Error>
     where
         S: Into<String>,
-        P: Into<String>,
-        F: Into<String>,
     {
-        let msg = json!(
-        {
-            "event": "subscribe",
-            "channel": "book",
-            "symbol": self.format_symbol(symbol.into(), et),
-            "prec": prec.into(),
-            "freq": freq.into(),
-            "len": len
-        });
+        let subscription = Subscription::books(et.format_symbol(symbol.into()), prec, freq, len)?;
+        self.subscribe(subscription);
 
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
-        }
+        Ok(())
     }
 
     pub fn subscribe_raw_books<S>(&mut self, symbol: S, et: EventType)
     where
         S: Into<String>,
     {
-        let msg = json!(
-        {
-            "event": "subscribe",
-            "channel": "book",
-            "prec": "R0",
-            "pair": self.format_symbol(symbol.into(), et)
-        });
+        let symbol = et.format_symbol(symbol.into());
+
+        self.subscribe(Subscription::RawBooks { symbol });
+    }
 
-        if let Err(error_msg) = self.sender.send(&msg.to_string()) {
-            self.error_handler(error_msg);
+    fn subscribe(&mut self, subscription: Subscription) {
+        if let Err(error_msg) = self.sender.tx.send(WsMessage::Subscribe(subscription)) {
+            self.error_handler(Error::Disconnected(error_msg.to_string()));
         }
     }
 
-    fn error_handler(&mut self, error_msg: BoxError) {
+    /// Unsubscribes from the channel with the given id. The subscription is not restored after a
+    /// reconnection anymore.
+    pub fn unsubscribe(&mut self, chan_id: u32) {
+        self.send_unsubscribe(Unsubscribe::Channel(chan_id));
+    }
+
+    /// Unsubscribes from the channel opened by `subscription`, e.g. one passed to
+    /// [`EventHandler::on_book_reset`].
+    ///
+    /// The channel must have been confirmed by a `subscribed` event, otherwise `on_error` is
+    /// called with an [`Error::Protocol`].
+    pub fn unsubscribe_from(&mut self, subscription: Subscription) {
+        self.send_unsubscribe(Unsubscribe::Subscription(subscription));
+    }
+
+    fn send_unsubscribe(&mut self, target: Unsubscribe) {
+        if let Err(error_msg) = self.sender.tx.send(WsMessage::Unsubscribe(target)) {
+            self.error_handler(Error::Disconnected(error_msg.to_string()));
+        }
+    }
+
+    /// Submits an order on the authenticated connection, without the round trip and nonce of
+    /// [`crate::orders::Orders::submit_order`].
+    ///
+    /// A client id is assigned to the order if it has none. The returned request resolves with
+    /// the `on-req` notification of the order.
+    pub fn submit_order(&mut self, order: &OrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::submit(order)?)
+    }
+
+    /// Updates an active order. The returned request resolves with its `ou-req` notification.
+    pub fn update_order(&mut self, order: &UpdateOrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::update(order)?)
+    }
+
+    /// Cancels an order. The returned request resolves with its `oc-req` notification.
+    pub fn cancel_order(&mut self, order: &CancelOrderForm) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::cancel(order)?)
+    }
+
+    /// Cancels several orders at once. The returned request resolves with the `oc_multi-req`
+    /// notification.
+    pub fn cancel_orders_multi(
+        &mut self,
+        orders: &CancelOrdersForm,
+    ) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::cancel_multi(orders)?)
+    }
+
+    /// Sends up to 75 order operations at once. The returned request resolves with the
+    /// `ox_multi-req` notification.
+    pub fn order_multi_op(&mut self, operations: &[OrderOperation]) -> Result<OrderRequest, Error> {
+        self.order(OrderMessage::multi_op(operations)?)
+    }
+
+    fn order(&mut self, message: OrderMessage) -> Result<OrderRequest, Error> {
+        let (text, pending, rx) = message.into_parts();
+
+        self.sender
+            .tx
+            .send(WsMessage::Order(text, pending))
+            .map_err(|e| Error::Disconnected(e.to_string()))?;
+
+        Ok(OrderRequest::new(rx))
+    }
+
+    fn error_handler(&mut self, error_msg: Error) {
         if let Some(ref mut h) = self.event_handler {
             h.on_error(error_msg);
         }
     }
 
-    fn format_symbol(&mut self, symbol: String, et: EventType) -> String {
-        let local_symbol = match et {
-            EventType::Funding => format!("f{}", symbol),
-            EventType::Trading => format!("t{}", symbol),
-        };
+    /// Sends the queued messages and dispatches the received ones to the event handler, until
+    /// the connection is closed with [`Sender::shutdown`].
+    ///
+    /// When the connection drops, the loop fails unless a reconnect policy is configured or the
+    /// server asked to reconnect, see [`ClientConfig::with_reconnect_policy`]. Frames which cannot be decoded are reported to
+    /// `on_error`, and the loop goes on.
+    pub fn event_loop(&mut self) -> Result<(), Error> {
+        loop {
+            match self.run() {
+                Ok(()) => return Ok(()),
+                Err(e) if is_connection_error(&e) => {
+                    let policy = match self.config.reconnect_policy() {
+                        Some(policy) => policy.clone(),
+                        // the server is about to restart, it is reconnected once regardless
+                        None if self.decoder.reconnect_requested() => ReconnectPolicy::requested(),
+                        None => return Err(e),
+                    };
 
-        local_symbol
+                    self.reconnect(e, &policy)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
-    pub fn event_loop(&mut self) -> Result<(), BoxError> {
+    fn run(&mut self) -> Result<(), Error> {
+        let socket = match self.socket {
+            Some((ref mut socket, _)) => socket,
+            None => return Err(Error::InvalidInput("Not connected".into())),
+        };
+
         loop {
-            if let Some(ref mut socket) = self.socket {
-                loop {
-                    match self.rx.try_recv() {
-                        Ok(msg) => match msg {
-                            WsMessage::Text(text) => {
-                                socket.0.write_message(Message::Text(text))?;
+            loop {
+                match self.rx.try_recv() {
+                    Ok(msg) => match msg {
+                        WsMessage::Text(text) => {
+                            socket.write_message(Message::Text(text))?;
+                        }
+                        // recorded first, so they are restored if the write fails
+                        WsMessage::Subscribe(subscription) => {
+                            let text = subscription.message();
+                            self.session.subscribed(subscription);
+                            socket.write_message(Message::Text(text))?;
+                        }
+                        WsMessage::Unsubscribe(target) => {
+                            match self.session.unsubscribed(target, &self.decoder) {
+                                Ok(text) => socket.write_message(Message::Text(text))?,
+                                Err(e) => {
+                                    if let Some(ref mut h) = self.event_handler {
+                                        h.on_error(e);
+                                    }
+                                }
                             }
-                            WsMessage::Close => {
-                                return socket.0.close(None).map_err(|e| e.into());
+                        }
+                        WsMessage::Auth(auth, text) => {
+                            self.session.authenticated(auth);
+                            self.orders.authenticating();
+                            socket.write_message(Message::Text(text))?;
+                        }
+                        WsMessage::Configure(flags) => {
+                            self.session.configured(flags);
+                            socket.write_message(Message::Text(conf_message(flags)))?;
+                        }
+                        WsMessage::Order(text, pending) => {
+                            let hold = self.decoder.maintenance();
+
+                            if let Some(text) = self.orders.send(text, pending, hold) {
+                                socket.write_message(Message::Text(text))?;
                             }
-                        },
-                        Err(mpsc::TryRecvError::Disconnected) => {
-                            bail!("Disconnected")
                         }
-                        Err(mpsc::TryRecvError::Empty) => break,
+                        WsMessage::Close => {
+                            return socket.close(None).map_err(|e| e.into());
+                        }
+                    },
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        return Err(Error::Disconnected("Sender dropped".into()));
                     }
+                    Err(mpsc::TryRecvError::Empty) => break,
                 }
+            }
 
-                let message = socket.0.read_message()?;
-
-                match message {
-                    Message::Text(text) => {
-                        if let Some(ref mut h) = self.event_handler {
-                            if text.find(INFO) != None {
-                                let event: NotificationEvent = from_str(&text)?;
-                                h.on_connect(event);
-                            } else if text.find(SUBSCRIBED) != None {
-                                let event: NotificationEvent = from_str(&text)?;
-                                h.on_subscribed(event);
-                            } else if text.find(AUTH).is_some() {
-                                let event: NotificationEvent = from_str(&text)?;
-                                h.on_auth(event);
-                            } else {
-                                let event: DataEvent = from_str(&text)?;
-                                if let DataEvent::HeartbeatEvent(_a, _b) = event {
-                                    continue;
-                                } else {
-                                    h.on_data_event(event);
-                                }
-                            }
-                        }
+            if let Some(ping) = self.watchdog.check(&self.decoder)? {
+                socket.write_message(Message::Text(ping))?;
+            }
+
+            let message = match socket.read_message() {
+                Ok(message) => message,
+                // the read timeout of the watchdog
+                Err(tungstenite::Error::Io(ref e)) if is_timeout(e) => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            match message {
+                Message::Text(text) => {
+                    if let Some(ref recorder) = self.recorder {
+                        recorder.record_frame(&text)?;
                     }
-                    Message::Binary(_) => {}
-                    Message::Ping(_) | Message::Pong(_) => {}
-                    Message::Close(e) => {
-                        bail!(format!("Disconnected {:?}", e));
+
+                    WebSockets::dispatch(
+                        &mut self.event_handler,
+                        &mut self.decoder,
+                        &mut self.orders,
+                        &text,
+                    );
+
+                    for reply in self.decoder.take_replies() {
+                        socket.write_message(Message::Text(reply))?;
+                    }
+
+                    if self.decoder.reconnect_requested() {
+                        return Err(Error::Disconnected(
+                            "Reconnect requested by the server".into(),
+                        ));
                     }
+
+                    if !self.decoder.maintenance() {
+                        for text in self.orders.release() {
+                            socket.write_message(Message::Text(text))?;
+                        }
+                    }
+                }
+                Message::Binary(_) => {}
+                Message::Ping(_) | Message::Pong(_) => {}
+                Message::Close(e) => {
+                    return Err(Error::Disconnected(format!("{:?}", e)));
+                }
+            }
+        }
+    }
+
+    fn reconnect(&mut self, error: Error, policy: &ReconnectPolicy) -> Result<(), Error> {
+        self.socket = None;
+        self.decoder.reset();
+        self.orders.reset();
+
+        if let Some(ref mut h) = self.event_handler {
+            h.on_disconnect(&error);
+
+            for book in self.session.books() {
+                h.on_book_reset(book);
+            }
+        }
+
+        let mut attempt = 1;
+
+        loop {
+            thread::sleep(policy.backoff(attempt));
+
+            match self.restore() {
+                Ok(()) => break,
+                Err(e) if !is_connection_error(&e) || !policy.allows(attempt + 1) => return Err(e),
+                Err(_) => attempt += 1,
+            }
+        }
+
+        if let Some(ref mut h) = self.event_handler {
+            h.on_reconnect();
+        }
+
+        Ok(())
+    }
+
+    // opens a new connection, authenticated and subscribed like the lost one
+    fn restore(&mut self) -> Result<(), Error> {
+        let (mut socket, response) = connect_websocket(&self.config)?;
+        let auth_message = self
+            .session
+            .auth()
+            .map(|auth| auth.message(self.config.nonce_provider().as_ref()))
+            .transpose()?;
+
+        for text in self.session.restore(auth_message) {
+            socket.write_message(Message::Text(text))?;
+        }
+
+        self.socket = Some((socket, response));
+        self.watchdog.reset();
+
+        Ok(())
+    }
+
+    // frames are decoded even without an event handler, to resolve the order requests. A frame
+    // which cannot be decoded is reported, without affecting the connection
+    fn dispatch(
+        handler: &mut Option<Box<dyn EventHandler>>,
+        decoder: &mut Decoder,
+        orders: &mut OrderRequests,
+        text: &str,
+    ) {
+        let events = match decoder.decode(text) {
+            Ok(events) => events,
+            Err(e) => {
+                if let Some(ref mut h) = handler {
+                    h.on_error(e);
                 }
+
+                return;
+            }
+        };
+
+        for event in events {
+            orders.resolve(&event);
+
+            if let Some(ref mut h) = handler {
+                WebSockets::handle(h.as_mut(), event);
+            }
+        }
+    }
+
+    fn handle(h: &mut dyn EventHandler, event: WebSocketEvent) {
+        match event {
+            WebSocketEvent::Info(event) => h.on_connect(event),
+            WebSocketEvent::Configured(event) => h.on_configured(event),
+            WebSocketEvent::Subscribed(event) => h.on_subscribed(event),
+            WebSocketEvent::Auth(event) => h.on_auth(event),
+            WebSocketEvent::Unsubscribed(event) => h.on_unsubscribed(event),
+            WebSocketEvent::SubscriptionError(event) => h.on_subscription_error(event),
+            WebSocketEvent::Data(channel, event, info) => {
+                h.on_channel_event(&channel, event, &info)
             }
+            WebSocketEvent::ChecksumMismatch(channel) => h.on_checksum_mismatch(&channel),
+            WebSocketEvent::Account(event, info) => h.on_account_event(event, &info),
+            WebSocketEvent::Pong(event) => h.on_pong(event),
+            WebSocketEvent::SequenceGap(gap) => h.on_sequence_gap(&gap),
+            WebSocketEvent::Unknown(frame) => h.on_unknown(&frame),
+            // connection events are not decoded from frames
+            _ => {}
         }
     }
 }
 
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
 #[derive(Clone)]
 pub struct Sender {
     tx: mpsc::Sender<WsMessage>,
 }
 
 impl Sender {
-    pub fn send(&self, raw: &str) -> Result<(), BoxError> {
-        Ok(self.tx.send(WsMessage::Text(raw.to_string()))?)
+    pub fn send(&self, raw: &str) -> Result<(), Error> {
+        self.tx
+            .send(WsMessage::Text(raw.to_string()))
+            .map_err(|e| Error::Disconnected(e.to_string()))
+    }
+
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.tx
+            .send(WsMessage::Close)
+            .map_err(|e| Error::Disconnected(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl Events {
+        fn push<E: Debug>(&self, event: E) {
+            self.0.lock().unwrap().push(format!("{:?}", event));
+        }
+    }
+
+    impl EventHandler for Events {
+        fn on_connect(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_auth(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_subscribed(&mut self, event: NotificationEvent) {
+            self.push(event);
+        }
+
+        fn on_data_event(&mut self, event: DataEvent) {
+            self.push(event);
+        }
+
+        fn on_error(&mut self, error: Error) {
+            self.push(error);
+        }
     }
 
-    pub fn shutdown(&self) -> Result<(), BoxError> {
-        Ok(self.tx.send(WsMessage::Close)?)
+    /// Serves `frames` to a single connection on a loopback port, then closes it.
+    fn server(frames: &'static [&'static str]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+
+            for frame in frames {
+                socket
+                    .write_message(Message::Text(frame.to_string()))
+                    .unwrap();
+            }
+
+            socket.close(None).unwrap();
+            while socket.read_message().is_ok() {}
+        });
+
+        url
+    }
+
+    #[test]
+    fn event_loop_goes_on_after_a_malformed_frame() {
+        let url = server(&[
+            r#"{"event":"subscribed","channel":"trades","chanId":17,"symbol":"tBTCUSD","pair":"BTCUSD"}"#,
+            r#"[17,"te",["malformed",1574694478808,0.005,7245.3]]"#,
+            r#"[17,"te",[401597393,1574694478808,0.005,7245.3]]"#,
+        ]);
+
+        let events = Events::default();
+        let received = events.0.clone();
+        let mut web_socket = WebSockets::with_config(ClientConfig::new().with_websocket_url(url));
+        web_socket.add_event_handler(events);
+        web_socket.connect().unwrap();
+
+        // without a reconnect policy, the loop only ends with the connection
+        assert!(matches!(
+            web_socket.event_loop(),
+            Err(Error::Disconnected(_)) | Err(Error::WebSocket(_))
+        ));
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3, "{:?}", received);
+        assert!(received[1].starts_with("Json"), "{}", received[1]);
+        assert!(received[2].starts_with("TradesTradingUpdateEvent(17,"));
+    }
+
+    fn message(subscription: &Subscription) -> serde_json::Value {
+        serde_json::from_str(&subscription.message()).unwrap()
+    }
+
+    #[test]
+    fn book_subscriptions_send_their_parameters() {
+        let subscription = Subscription::books(
+            "tBTCUSD".into(),
+            BookPrecision::P2,
+            BookFrequency::F1,
+            BookLength::L100,
+        )
+        .unwrap();
+
+        assert_eq!(
+            message(&subscription),
+            json!({
+                "event": "subscribe",
+                "channel": "book",
+                "symbol": "tBTCUSD",
+                "prec": "P2",
+                "freq": "F1",
+                "len": 100
+            })
+        );
+
+        let subscription = Subscription::RawBooks {
+            symbol: "fUSD".into(),
+        };
+        assert_eq!(
+            message(&subscription),
+            json!({"event": "subscribe", "channel": "book", "prec": "R0", "symbol": "fUSD"})
+        );
+    }
+
+    #[test]
+    fn raw_books_are_not_subscribed_as_books() {
+        let mut web_socket = WebSockets::new();
+
+        assert!(matches!(
+            web_socket.subscribe_books(
+                "BTCUSD",
+                EventType::Trading,
+                BookPrecision::R0,
+                BookFrequency::F0,
+                BookLength::L25,
+            ),
+            Err(Error::InvalidInput(_))
+        ));
+        // nothing was queued for the connection
+        assert!(web_socket.rx.try_recv().is_err());
     }
 }